@@ -0,0 +1,646 @@
+//! Configuration files with layered defaults
+//!
+//! Options that would otherwise be repeated on every invocation can be kept
+//! in a user file (`~/.config/md2pdf/config.toml`) and in a project file
+//! (`md2pdf.toml`) found in the input's directory or one of its parents.
+//! Layers apply in this order, each overriding the one before:
+//!
+//! 1. built-in defaults
+//! 2. the user file
+//! 3. the project file
+//! 4. front matter (document metadata and variables only)
+//! 5. the command line
+//!
+//! Metadata and variables from configuration files go into
+//! [`ConversionOptions::metadata_defaults`] so front matter can override them,
+//! and the stylesheet into [`ConversionOptions::custom_css_default`] so a book
+//! manifest's stylesheet can.
+//!
+//! A project file travels with the documents it sits next to, so it may not
+//! set [`USER_ONLY_KEYS`]: diagram commands run programs, and raw HTML
+//! handling decides whether scripts in the markdown survive.
+
+use crate::error::{Md2PdfError, Result};
+use crate::highlight;
+use crate::html::RawHtml;
+use crate::markdown::DocumentMetadata;
+use crate::pdf::{parse_length, NetworkPolicy, Orientation, PaperSize};
+use crate::project::PROJECT_FILE;
+use crate::ConversionOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// File name of the user configuration, inside the `md2pdf` config directory
+pub const USER_CONFIG_FILE: &str = "config.toml";
+
+/// Keys accepted in the user file but rejected in a project file
+pub const USER_ONLY_KEYS: &[&str] = &["diagram-commands", "raw-html"];
+
+/// Settings from a configuration file; anything left out keeps the value of
+/// the layer below
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// Custom CSS file
+    pub css: Option<PathBuf>,
+    /// Inline local images as data URIs
+    pub embed_images: Option<bool>,
+    /// Treat recoverable problems as errors
+    pub strict: Option<bool>,
+    /// Insert a table of contents
+    pub toc: Option<bool>,
+    /// Deepest heading level in the table of contents
+    pub toc_depth: Option<u8>,
+    /// Code block highlight theme, or `none`
+    pub highlight_theme: Option<String>,
+    /// Raw HTML handling: `sanitize`, `allow` or `escape`; user file only
+    pub raw_html: Option<String>,
+    /// Mermaid library bundle used instead of the bundled build
+    pub mermaid_js: Option<PathBuf>,
+    /// Diagram commands keyed by language; an empty command turns a
+    /// language off. User file only
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub diagram_commands: BTreeMap<String, String>,
+    /// Default document metadata
+    #[serde(default, skip_serializing_if = "MetadataConfig::is_empty")]
+    pub metadata: MetadataConfig,
+    /// Default template variables
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+    /// Page layout and rendering
+    #[serde(default)]
+    pub pdf: PdfSection,
+    /// Book manifest, read by [`crate::project::Book`]
+    #[serde(default, skip_serializing)]
+    pub book: Option<toml::Value>,
+}
+
+/// The `[metadata]` table
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetadataConfig {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub date: Option<String>,
+    pub subject: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub keywords: Vec<String>,
+    pub lang: Option<String>,
+}
+
+impl MetadataConfig {
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The `[pdf]` table
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PdfSection {
+    /// Named paper size
+    pub paper: Option<String>,
+    /// Custom paper width
+    pub paper_width: Option<Length>,
+    /// Custom paper height
+    pub paper_height: Option<Length>,
+    /// Landscape orientation
+    pub landscape: Option<bool>,
+    /// All four margins at once; the individual margins take precedence
+    pub margin: Option<Length>,
+    pub margin_top: Option<Length>,
+    pub margin_bottom: Option<Length>,
+    pub margin_left: Option<Length>,
+    pub margin_right: Option<Length>,
+    /// Page rendering scale
+    pub scale: Option<f64>,
+    /// Print background graphics
+    pub print_background: Option<bool>,
+    /// Header template: inline HTML or a file path
+    pub header_template: Option<String>,
+    /// Footer template: inline HTML or a file path
+    pub footer_template: Option<String>,
+    /// Deepest heading level turned into a bookmark
+    pub outline_depth: Option<u8>,
+    /// Seconds to wait for the document to render
+    pub render_timeout: Option<f64>,
+    /// Wait for `window.md2pdfReady`
+    pub wait_for_ready_signal: Option<bool>,
+    /// Network policy: `offline`, `local` or `all`
+    pub network: Option<String>,
+    /// Fail when the network policy blocks a request
+    pub fail_on_blocked: Option<bool>,
+}
+
+/// A length in inches, or a string with a unit such as `"2cm"`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Length {
+    Inches(f64),
+    Text(String),
+}
+
+impl Length {
+    /// The length in inches
+    pub fn inches(&self) -> Result<f64> {
+        match self {
+            Length::Inches(inches) => Ok(*inches),
+            Length::Text(text) => parse_length(text),
+        }
+    }
+
+    /// A length given in inches, written in the first unit that reads back
+    /// as exactly the same length
+    fn from_inches(inches: f64) -> Self {
+        ["in", "cm", "mm", "pt"]
+            .into_iter()
+            .find_map(|unit| {
+                let value = inches / parse_length(&format!("1{}", unit)).ok()?;
+                let number = format!("{:.4}", value);
+                let text = format!(
+                    "{}{}",
+                    number.trim_end_matches('0').trim_end_matches('.'),
+                    unit
+                );
+                (parse_length(&text).ok()? == inches).then_some(text)
+            })
+            .map_or_else(|| Length::Text(format!("{}in", inches)), Length::Text)
+    }
+}
+
+impl Config {
+    /// Read a configuration file
+    ///
+    /// Relative paths in it (stylesheets, templates, the Mermaid bundle) are
+    /// resolved against the file's directory.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|e| Md2PdfError::FileRead {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
+        let mut config: Config = toml::from_str(&content).map_err(|e| Md2PdfError::Config {
+            path: path.to_path_buf(),
+            message: e.to_string(),
+        })?;
+
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for file in [&mut config.css, &mut config.mermaid_js]
+            .into_iter()
+            .flatten()
+        {
+            *file = dir.join(&*file);
+        }
+        // Templates may be inline HTML; only values naming a file are paths
+        for template in [
+            &mut config.pdf.header_template,
+            &mut config.pdf.footer_template,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            let file = dir.join(&*template);
+            if file.is_file() {
+                *template = file.to_string_lossy().to_string();
+            }
+        }
+        Ok(config)
+    }
+
+    /// Read a project file, rejecting [`USER_ONLY_KEYS`]
+    pub fn load_project(path: &Path) -> Result<Self> {
+        let config = Self::load(path)?;
+        let set = [
+            !config.diagram_commands.is_empty(),
+            config.raw_html.is_some(),
+        ];
+        if let Some((key, _)) = USER_ONLY_KEYS.iter().zip(set).find(|(_, set)| *set) {
+            return Err(Md2PdfError::Config {
+                path: path.to_path_buf(),
+                message: format!(
+                    "`{}` is not allowed in a project file; set it in {} or on the command line",
+                    key,
+                    user_config_path()
+                        .map_or(USER_CONFIG_FILE.into(), |path| path.display().to_string())
+                ),
+            });
+        }
+        Ok(config)
+    }
+
+    /// Overlay the settings of this file on `options`
+    pub fn apply(&self, options: &mut ConversionOptions) -> Result<()> {
+        if let Some(css) = &self.css {
+            options.custom_css_default = Some(css.to_string_lossy().to_string());
+        }
+        let flags = [
+            (&mut options.embed_images, self.embed_images),
+            (&mut options.strict, self.strict),
+            (&mut options.toc, self.toc),
+        ];
+        for (option, value) in flags {
+            if let Some(value) = value {
+                *option = value;
+            }
+        }
+        if let Some(depth) = self.toc_depth {
+            options.toc_depth = depth;
+        }
+        if let Some(theme) = &self.highlight_theme {
+            options.highlight_theme = if theme == "none" {
+                None
+            } else {
+                highlight::theme_css(theme)?;
+                Some(theme.clone())
+            };
+        }
+        if let Some(mode) = &self.raw_html {
+            options.raw_html = mode.parse::<RawHtml>()?;
+        }
+        if let Some(script) = &self.mermaid_js {
+            options.mermaid_script = Some(script.clone());
+        }
+        for (language, command) in &self.diagram_commands {
+            if command.trim().is_empty() {
+                options.diagram_commands.remove(language);
+            } else {
+                options
+                    .diagram_commands
+                    .insert(language.clone(), command.clone());
+            }
+        }
+
+        options.metadata_defaults.merge(&DocumentMetadata {
+            title: self.metadata.title.clone(),
+            author: self.metadata.author.clone(),
+            date: self.metadata.date.clone(),
+            subject: self.metadata.subject.clone(),
+            keywords: self.metadata.keywords.clone(),
+            lang: self.metadata.lang.clone(),
+            extra: self.variables.clone(),
+        });
+
+        self.pdf.apply(&mut options.pdf_config)
+    }
+
+    /// The settings `options` amount to, with `metadata` as the effective
+    /// document metadata
+    pub fn from_options(options: &ConversionOptions, metadata: &DocumentMetadata) -> Self {
+        let pdf_config = &options.pdf_config;
+        let (paper, paper_width, paper_height) = match pdf_config.paper_size {
+            PaperSize::Custom { width, height } => (
+                None,
+                Some(Length::from_inches(width)),
+                Some(Length::from_inches(height)),
+            ),
+            paper => (Some(paper.to_string()), None, None),
+        };
+
+        let mut variables = metadata.extra.clone();
+        variables.extend(options.variables.clone());
+
+        Self {
+            css: options.css_path().map(PathBuf::from),
+            embed_images: Some(options.embed_images),
+            strict: Some(options.strict),
+            toc: Some(options.toc),
+            toc_depth: Some(options.toc_depth),
+            highlight_theme: Some(
+                options
+                    .highlight_theme
+                    .clone()
+                    .unwrap_or_else(|| "none".to_string()),
+            ),
+            raw_html: Some(options.raw_html.to_string()),
+            mermaid_js: options.mermaid_script.clone(),
+            diagram_commands: options.diagram_commands.clone(),
+            metadata: MetadataConfig {
+                title: metadata.title.clone(),
+                author: metadata.author.clone(),
+                date: metadata.date.clone(),
+                subject: metadata.subject.clone(),
+                keywords: metadata.keywords.clone(),
+                lang: metadata.lang.clone(),
+            },
+            variables,
+            pdf: PdfSection {
+                paper,
+                paper_width,
+                paper_height,
+                landscape: Some(pdf_config.orientation == Orientation::Landscape),
+                margin: None,
+                margin_top: Some(Length::from_inches(pdf_config.margin_top)),
+                margin_bottom: Some(Length::from_inches(pdf_config.margin_bottom)),
+                margin_left: Some(Length::from_inches(pdf_config.margin_left)),
+                margin_right: Some(Length::from_inches(pdf_config.margin_right)),
+                scale: Some(pdf_config.scale),
+                print_background: Some(pdf_config.print_background),
+                header_template: pdf_config.header_template.clone(),
+                footer_template: pdf_config.footer_template.clone(),
+                outline_depth: Some(pdf_config.outline_depth),
+                render_timeout: Some(pdf_config.render_timeout.as_secs_f64()),
+                wait_for_ready_signal: Some(pdf_config.wait_for_ready_signal),
+                network: Some(pdf_config.network_policy.to_string()),
+                fail_on_blocked: Some(pdf_config.fail_on_blocked_request),
+            },
+            book: None,
+        }
+    }
+
+    /// The configuration as TOML
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("configuration values serialize to TOML")
+    }
+}
+
+impl PdfSection {
+    fn apply(&self, pdf_config: &mut crate::pdf::PdfConfig) -> Result<()> {
+        if let Some(paper) = &self.paper {
+            pdf_config.paper_size = paper.parse()?;
+        }
+        if self.paper_width.is_some() || self.paper_height.is_some() {
+            let (width, height) = pdf_config.paper_size.dimensions();
+            pdf_config.paper_size = PaperSize::Custom {
+                width: self
+                    .paper_width
+                    .as_ref()
+                    .map_or(Ok(width), Length::inches)?,
+                height: self
+                    .paper_height
+                    .as_ref()
+                    .map_or(Ok(height), Length::inches)?,
+            };
+        }
+        if let Some(landscape) = self.landscape {
+            pdf_config.orientation = if landscape {
+                Orientation::Landscape
+            } else {
+                Orientation::Portrait
+            };
+        }
+
+        let margins = [
+            (&mut pdf_config.margin_top, &self.margin_top),
+            (&mut pdf_config.margin_bottom, &self.margin_bottom),
+            (&mut pdf_config.margin_left, &self.margin_left),
+            (&mut pdf_config.margin_right, &self.margin_right),
+        ];
+        for (margin, value) in margins {
+            if let Some(length) = value.as_ref().or(self.margin.as_ref()) {
+                *margin = length.inches()?;
+            }
+        }
+
+        if let Some(scale) = self.scale {
+            pdf_config.scale = scale;
+        }
+        if let Some(print_background) = self.print_background {
+            pdf_config.print_background = print_background;
+        }
+        let templates = [
+            (&mut pdf_config.header_template, &self.header_template),
+            (&mut pdf_config.footer_template, &self.footer_template),
+        ];
+        for (template, value) in templates {
+            if let Some(value) = value {
+                *template = Some(crate::template::load_page_template(value)?);
+            }
+        }
+        if let Some(depth) = self.outline_depth {
+            pdf_config.outline_depth = depth;
+        }
+        if let Some(seconds) = self.render_timeout {
+            pdf_config.render_timeout = Duration::try_from_secs_f64(seconds).map_err(|_| {
+                Md2PdfError::InvalidPdfConfig(format!("invalid render timeout: {}", seconds))
+            })?;
+        }
+        if let Some(wait) = self.wait_for_ready_signal {
+            pdf_config.wait_for_ready_signal = wait;
+        }
+        if let Some(network) = &self.network {
+            pdf_config.network_policy = network.parse::<NetworkPolicy>()?;
+        }
+        if let Some(fail) = self.fail_on_blocked {
+            pdf_config.fail_on_blocked_request = fail;
+        }
+        Ok(())
+    }
+}
+
+/// Path of the user configuration file, whether or not it exists
+///
+/// `$XDG_CONFIG_HOME/md2pdf/config.toml`, falling back to
+/// `~/.config/md2pdf/config.toml`.
+pub fn user_config_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("md2pdf").join(USER_CONFIG_FILE))
+}
+
+/// The nearest `md2pdf.toml` in `start`'s directory or one of its parents
+///
+/// `start` may be a file or a directory.
+pub fn find_project_file(start: &Path) -> Option<PathBuf> {
+    let start = start.canonicalize().ok()?;
+    let dir = if start.is_dir() {
+        start.as_path()
+    } else {
+        start.parent()?
+    };
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_FILE))
+        .find(|file| file.is_file())
+}
+
+/// Configuration files that apply to a conversion of `input`, lowest
+/// precedence first
+///
+/// Without an input, the project file is looked up from the current
+/// directory.
+pub fn config_files(input: Option<&Path>) -> Vec<PathBuf> {
+    let start = input.unwrap_or(Path::new("."));
+    user_config_path()
+        .filter(|path| path.is_file())
+        .into_iter()
+        .chain(find_project_file(start))
+        .collect()
+}
+
+/// Built-in defaults overlaid with each configuration file in turn
+///
+/// Files named `md2pdf.toml` are read as project files.
+pub fn load_options(files: &[PathBuf]) -> Result<ConversionOptions> {
+    let mut options = ConversionOptions::default();
+    for file in files {
+        let config = if file.file_name() == Some(PROJECT_FILE.as_ref()) {
+            Config::load_project(file)?
+        } else {
+            Config::load(file)?
+        };
+        config.apply(&mut options)?;
+    }
+    Ok(options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_resolves_paths_against_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("header.html"), "<span>{{title}}</span>").unwrap();
+        let path = dir.path().join(PROJECT_FILE);
+        fs::write(
+            &path,
+            r#"
+css = "style/brand.css"
+
+[pdf]
+header-template = "header.html"
+footer-template = "<span>{{pageNumber}}</span>"
+
+[book]
+chapters = ["intro.md"]
+"#,
+        )
+        .unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.css, Some(dir.path().join("style/brand.css")));
+        assert_eq!(
+            config.pdf.header_template,
+            Some(dir.path().join("header.html").to_string_lossy().to_string())
+        );
+        assert_eq!(
+            config.pdf.footer_template.as_deref(),
+            Some("<span>{{pageNumber}}</span>")
+        );
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PROJECT_FILE);
+        fs::write(&path, "[pdf]\nmargn-top = \"2cm\"\n").unwrap();
+
+        let err = Config::load(&path).unwrap_err();
+        assert!(matches!(err, Md2PdfError::Config { .. }));
+        assert!(err.to_string().contains("margn-top"));
+    }
+
+    #[test]
+    fn test_project_file_rejects_user_only_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join(USER_CONFIG_FILE);
+        let project = dir.path().join(PROJECT_FILE);
+        let trusted = "raw-html = \"allow\"\n\n[diagram-commands]\ndot = \"dot -Tsvg\"\n";
+        fs::write(&user, trusted).unwrap();
+
+        let options = load_options(std::slice::from_ref(&user)).unwrap();
+        assert_eq!(options.raw_html, RawHtml::Allow);
+        assert_eq!(options.diagram_commands["dot"], "dot -Tsvg");
+
+        for content in [trusted, "[diagram-commands]\ndot = \"\"\n"] {
+            fs::write(&project, content).unwrap();
+            let err = load_options(&[user.clone(), project.clone()]).unwrap_err();
+            assert!(matches!(err, Md2PdfError::Config { ref path, .. } if *path == project));
+            assert!(err.to_string().contains("not allowed in a project file"));
+        }
+        fs::write(&project, "toc = true\n").unwrap();
+        assert!(load_options(&[user, project]).unwrap().toc);
+    }
+
+    #[test]
+    fn test_layers_override_in_order() {
+        let user: Config = toml::from_str(
+            r#"
+toc = true
+highlight-theme = "none"
+raw-html = "escape"
+
+[metadata]
+author = "User"
+
+[variables]
+team = "Docs"
+
+[pdf]
+paper = "letter"
+margin = "1in"
+"#,
+        )
+        .unwrap();
+        let project: Config = toml::from_str(
+            r#"
+[metadata]
+author = "Project"
+
+[pdf]
+margin-top = "2cm"
+landscape = true
+"#,
+        )
+        .unwrap();
+
+        let mut options = ConversionOptions::default();
+        user.apply(&mut options).unwrap();
+        project.apply(&mut options).unwrap();
+
+        assert!(options.toc);
+        assert_eq!(options.highlight_theme, None);
+        assert_eq!(options.raw_html, RawHtml::Escape);
+        assert_eq!(options.metadata_defaults.author.as_deref(), Some("Project"));
+        assert_eq!(options.metadata_defaults.extra["team"], "Docs");
+        assert_eq!(options.pdf_config.paper_size, PaperSize::Letter);
+        assert_eq!(options.pdf_config.orientation, Orientation::Landscape);
+        assert!((options.pdf_config.margin_top - 2.0 / 2.54).abs() < 1e-9);
+        assert_eq!(options.pdf_config.margin_left, 1.0);
+        // Front matter and the command line sit above configuration files
+        assert!(options.metadata.author.is_none());
+    }
+
+    #[test]
+    fn test_invalid_values_are_reported() {
+        let mut options = ConversionOptions::default();
+        let config: Config = toml::from_str("highlight-theme = \"no-such-theme\"").unwrap();
+        assert!(config.apply(&mut options).is_err());
+        let config: Config = toml::from_str("[pdf]\nmargin = \"2 furlongs\"").unwrap();
+        assert!(config.apply(&mut options).is_err());
+    }
+
+    #[test]
+    fn test_find_project_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let nested = dir.path().join("docs/guide");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(nested.join("index.md"), "# Guide").unwrap();
+        fs::write(dir.path().join(PROJECT_FILE), "toc = true\n").unwrap();
+
+        let found = find_project_file(&nested.join("index.md")).unwrap();
+        assert_eq!(found, dir.path().canonicalize().unwrap().join(PROJECT_FILE));
+    }
+
+    #[test]
+    fn test_from_options_round_trips() {
+        let mut options = ConversionOptions::default();
+        options.pdf_config.margin_top = 1.0;
+        options
+            .variables
+            .insert("version".to_string(), "2".to_string());
+        let config = Config::from_options(&options, &DocumentMetadata::default());
+        let text = config.to_toml();
+        assert!(text.contains("margin-top = \"1in\""));
+        assert!(text.contains("version = \"2\""));
+
+        let parsed: Config = toml::from_str(&text).unwrap();
+        let mut applied = ConversionOptions::default();
+        parsed.apply(&mut applied).unwrap();
+        assert_eq!(applied.pdf_config, options.pdf_config);
+    }
+}
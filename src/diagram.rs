@@ -0,0 +1,565 @@
+//! Diagram code blocks rendered to inline SVG
+//!
+//! Fenced code blocks in a diagram language (` ```mermaid `, ` ```dot `,
+//! ` ```plantuml `, ...) are handed to a [`DiagramRenderer`] and replaced by
+//! the SVG it returns. Graphviz, PlantUML and the like run as local commands,
+//! which are only used for languages configured explicitly, since a command
+//! runs on input taken from the document; Mermaid runs inside the headless
+//! Chrome used for printing, from a build bundled with md2pdf unless another
+//! one is configured. Rendered diagrams are cached by content, so
+//! unchanged blocks are only rendered once per process.
+//!
+//! Renderers are not trusted to produce safe markup: their SVG is cleaned
+//! against an allowlist of drawing elements and attributes, which drops
+//! scripts, event handlers and `<foreignObject>` HTML.
+
+use crate::error::{Md2PdfError, Result};
+use crate::pdf::Renderer;
+use crate::template::escape_html;
+use log::debug;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+
+/// Renders the source of a diagram code block to SVG
+pub trait DiagramRenderer: Send + Sync {
+    /// Whether this renderer handles code blocks in `language`
+    fn handles(&self, language: &str) -> bool;
+
+    /// Render `source` to an SVG document
+    fn render(&self, language: &str, source: &str) -> Result<String>;
+
+    /// Identifies the renderer and its configuration in cache keys, so the
+    /// same source rendered differently is cached separately
+    fn cache_key(&self) -> String;
+}
+
+/// Renders diagrams by piping the source through a local command that
+/// writes SVG to stdout, e.g. `dot -Tsvg`
+///
+/// The command runs outside the browser, so the network policy does not
+/// apply to it: PlantUML's `!include` and `!includeurl`, for example, can
+/// still read local files and fetch URLs.
+#[derive(Debug, Clone)]
+pub struct CommandRenderer {
+    language: String,
+    program: String,
+    args: Vec<String>,
+}
+
+impl CommandRenderer {
+    /// Create a renderer for `language` from a whitespace-separated command line
+    pub fn new(language: &str, command: &str) -> Result<Self> {
+        let mut words = command.split_whitespace().map(str::to_string);
+        let program = words.next().ok_or_else(|| Md2PdfError::Diagram {
+            language: language.to_string(),
+            message: "empty diagram command".to_string(),
+        })?;
+        Ok(Self {
+            language: language.to_string(),
+            program,
+            args: words.collect(),
+        })
+    }
+}
+
+impl DiagramRenderer for CommandRenderer {
+    fn handles(&self, language: &str) -> bool {
+        language.eq_ignore_ascii_case(&self.language)
+    }
+
+    fn render(&self, language: &str, source: &str) -> Result<String> {
+        let error = |message: String| Md2PdfError::Diagram {
+            language: language.to_string(),
+            message,
+        };
+
+        debug!("Running diagram command: {} {:?}", self.program, self.args);
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| error(format!("failed to run '{}': {}", self.program, e)))?;
+
+        // Write from another thread so a command that fills its output pipe
+        // before reading all input can't deadlock
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let input = source.to_string();
+        let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+        let output = child
+            .wait_with_output()
+            .map_err(|e| error(format!("failed to run '{}': {}", self.program, e)))?;
+        let _ = writer.join();
+
+        if !output.status.success() {
+            return Err(error(format!(
+                "'{}' exited with {}: {}",
+                self.program,
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        String::from_utf8(output.stdout)
+            .map_err(|_| error(format!("'{}' did not write UTF-8 SVG", self.program)))
+    }
+
+    fn cache_key(&self) -> String {
+        format!("command:{} {}", self.program, self.args.join(" "))
+    }
+}
+
+/// The Mermaid build compiled in with the `bundled-mermaid` feature
+/// (Mermaid 11.2.0, see `vendor/mermaid`)
+#[cfg(feature = "bundled-mermaid")]
+pub const BUNDLED_MERMAID: Option<&str> = Some(include_str!("../vendor/mermaid/mermaid.min.js"));
+
+/// The Mermaid build compiled in with the `bundled-mermaid` feature
+#[cfg(not(feature = "bundled-mermaid"))]
+pub const BUNDLED_MERMAID: Option<&str> = None;
+
+/// Renders Mermaid diagrams with the Mermaid library inside headless Chrome
+pub struct MermaidRenderer<'a> {
+    renderer: &'a Renderer,
+    script: Cow<'static, str>,
+    /// Hash of the script, computed once since bundles are megabytes long
+    cache_key: String,
+}
+
+impl<'a> MermaidRenderer<'a> {
+    /// Create a renderer that loads the Mermaid library from `script_path`
+    /// (a `mermaid.min.js` bundle) into pages of `renderer`'s browser
+    pub fn new(renderer: &'a Renderer, script_path: &Path) -> Result<Self> {
+        let script = fs::read_to_string(script_path).map_err(|e| Md2PdfError::FileRead {
+            path: script_path.to_path_buf(),
+            source: e,
+        })?;
+        Ok(Self::with_script(renderer, script.into()))
+    }
+
+    /// Create a renderer using [`BUNDLED_MERMAID`], if it was compiled in
+    pub fn bundled(renderer: &'a Renderer) -> Option<Self> {
+        BUNDLED_MERMAID.map(|script| Self::with_script(renderer, script.into()))
+    }
+
+    fn with_script(renderer: &'a Renderer, script: Cow<'static, str>) -> Self {
+        let mut hasher = DefaultHasher::new();
+        script.hash(&mut hasher);
+        Self {
+            renderer,
+            script,
+            cache_key: format!("mermaid:{:x}", hasher.finish()),
+        }
+    }
+}
+
+impl DiagramRenderer for MermaidRenderer<'_> {
+    fn handles(&self, language: &str) -> bool {
+        language.eq_ignore_ascii_case("mermaid")
+    }
+
+    fn render(&self, language: &str, source: &str) -> Result<String> {
+        // `</script` inside the bundle would end the inline script early
+        let page = format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><script>{}</script></head><body></body></html>",
+            self.script.replace("</script", "<\\/script")
+        );
+        self.renderer
+            .evaluate(&page, &mermaid_call(source))
+            .map_err(|e| Md2PdfError::Diagram {
+                language: language.to_string(),
+                message: e.to_string(),
+            })
+    }
+
+    fn cache_key(&self) -> String {
+        self.cache_key.clone()
+    }
+}
+
+/// Script rendering `source` with Mermaid and returning the SVG
+///
+/// Labels are drawn as SVG text rather than HTML in `<foreignObject>`, which
+/// [`sanitize_svg`] removes. The `<style>` element Mermaid emits is removed
+/// too, so the script resolves it into `style` attributes first: every
+/// property in [`MERMAID_STYLED`] whose computed value changes without the
+/// stylesheet is written onto the element.
+///
+/// Mermaid uses the id for the `<svg>` element and prefixes the ids of
+/// markers and styles inside it, so each diagram gets an id derived from its
+/// source; a fixed id would make every diagram in a document point at the
+/// first one's arrowheads and styles.
+fn mermaid_call(source: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    format!(
+        r#"(async () => {{
+    mermaid.initialize({{
+        startOnLoad: false,
+        securityLevel: "strict",
+        htmlLabels: false,
+        flowchart: {{ htmlLabels: false }},
+    }});
+    const {{ svg }} = await mermaid.render("md2pdf-mermaid-{:x}", {});
+    const host = document.createElement("div");
+    host.innerHTML = svg;
+    document.body.appendChild(host);
+    const root = host.querySelector("svg");
+    const properties = {}.split(" ");
+    const elements = [root, ...root.querySelectorAll("*")];
+    const styled = elements.map((element) => {{
+        const style = getComputedStyle(element);
+        return properties.map((property) => style.getPropertyValue(property));
+    }});
+    root.querySelectorAll("style").forEach((style) => style.remove());
+    elements.forEach((element, i) => {{
+        if (!element.isConnected) return;
+        const style = getComputedStyle(element);
+        properties.forEach((property, j) => {{
+            if (style.getPropertyValue(property) !== styled[i][j]) {{
+                element.style.setProperty(property, styled[i][j]);
+            }}
+        }});
+    }});
+    host.remove();
+    return root.outerHTML;
+}})()"#,
+        hasher.finish(),
+        js_string(source),
+        js_string(
+            &MERMAID_STYLED
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        ),
+    )
+}
+
+/// Properties Mermaid's stylesheet sets that are carried over into `style`
+/// attributes, separated by whitespace
+const MERMAID_STYLED: &str = "\
+    fill fill-opacity stroke stroke-width stroke-opacity stroke-dasharray \
+    stroke-linecap stroke-linejoin opacity color font-family font-size \
+    font-style font-weight text-anchor dominant-baseline text-decoration \
+    display visibility";
+
+/// The diagram renderers available to a conversion
+#[derive(Default)]
+pub struct Diagrams<'a> {
+    renderers: Vec<Box<dyn DiagramRenderer + 'a>>,
+}
+
+impl std::fmt::Debug for Diagrams<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Diagrams")
+            .field("renderers", &self.renderers.len())
+            .finish()
+    }
+}
+
+impl<'a> Diagrams<'a> {
+    /// No diagram renderers; diagram blocks stay code blocks
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set up command renderers for each `language = command` pair, plus
+    /// Mermaid from the given script bundle or else the bundled build
+    pub fn from_config(
+        commands: &BTreeMap<String, String>,
+        mermaid_script: Option<&Path>,
+        renderer: &'a Renderer,
+    ) -> Result<Self> {
+        let mut diagrams = Self::new();
+        for (language, command) in commands {
+            diagrams.add(CommandRenderer::new(language, command)?);
+        }
+        match mermaid_script {
+            Some(path) => diagrams.add(MermaidRenderer::new(renderer, path)?),
+            None => {
+                if let Some(mermaid) = MermaidRenderer::bundled(renderer) {
+                    diagrams.add(mermaid);
+                }
+            }
+        }
+        Ok(diagrams)
+    }
+
+    /// Add a renderer; earlier renderers take precedence
+    pub fn add(&mut self, renderer: impl DiagramRenderer + 'a) {
+        self.renderers.push(Box::new(renderer));
+    }
+
+    /// Whether some renderer handles `language`
+    pub fn handles(&self, language: &str) -> bool {
+        self.renderers.iter().any(|r| r.handles(language))
+    }
+
+    /// Render a diagram to inline SVG, using the cache when possible
+    ///
+    /// Returns `None` when no renderer handles `language`.
+    pub fn render(&self, language: &str, source: &str) -> Option<Result<String>> {
+        let renderer = self.renderers.iter().find(|r| r.handles(language))?;
+        let key = (
+            format!("{}|{}", renderer.cache_key(), language),
+            source.to_string(),
+        );
+
+        if let Some(svg) = lock_cache().get(&key) {
+            debug!("Using cached {} diagram", language);
+            return Some(Ok(svg.clone()));
+        }
+
+        Some(renderer.render(language, source).map(|svg| {
+            let svg = sanitize_svg(&inline_svg(&svg));
+            lock_cache().insert(key, svg.clone());
+            svg
+        }))
+    }
+}
+
+type Cache = HashMap<(String, String), String>;
+
+/// Rendered diagrams keyed by renderer and source
+fn lock_cache() -> std::sync::MutexGuard<'static, Cache> {
+    static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+    CACHE
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Wrap rendered SVG in a figure, dropping the XML prolog and doctype that
+/// standalone SVG files carry
+pub fn diagram_figure(language: &str, svg: &str) -> String {
+    format!(
+        "<figure class=\"diagram diagram-{}\">{}</figure>\n",
+        escape_html(&language.to_ascii_lowercase()),
+        svg
+    )
+}
+
+/// SVG elements kept in rendered diagrams, separated by whitespace
+const SVG_TAGS: &str = "\
+    svg g defs symbol use title desc a switch path rect circle ellipse \
+    line polyline polygon text tspan textPath image marker pattern clipPath mask \
+    linearGradient radialGradient stop filter feBlend feColorMatrix \
+    feComponentTransfer feComposite feDropShadow feFlood feFuncA feFuncB feFuncG \
+    feFuncR feGaussianBlur feMerge feMergeNode feMorphology feOffset";
+
+/// Attributes kept on any SVG element, separated by whitespace: geometry,
+/// presentation and references between elements
+const SVG_ATTRIBUTES: &str = "\
+    id class style transform viewBox preserveAspectRatio width height x y x1 y1 \
+    x2 y2 cx cy r rx ry d points pathLength dx dy rotate href role aria-label \
+    aria-roledescription fill fill-opacity fill-rule stroke stroke-width \
+    stroke-opacity stroke-dasharray stroke-dashoffset stroke-linecap \
+    stroke-linejoin stroke-miterlimit opacity color font-family font-size \
+    font-style font-weight text-anchor dominant-baseline alignment-baseline \
+    baseline-shift letter-spacing text-decoration writing-mode visibility \
+    display clip-path clip-rule mask filter marker-start marker-mid marker-end \
+    markerWidth markerHeight markerUnits refX refY orient patternUnits \
+    patternContentUnits patternTransform gradientUnits gradientTransform \
+    spreadMethod fx fy offset stop-color stop-opacity clipPathUnits maskUnits \
+    maskContentUnits filterUnits primitiveUnits in in2 result mode operator k1 \
+    k2 k3 k4 stdDeviation flood-color flood-opacity values type tableValues \
+    slope intercept amplitude exponent radius lengthAdjust textLength \
+    startOffset target";
+
+/// Clean rendered SVG against [`SVG_TAGS`] and [`SVG_ATTRIBUTES`]
+///
+/// Scripts, event handler attributes, `<foreignObject>` and anything else
+/// not on the lists are removed, as are `javascript:` links. `<style>`
+/// elements are dropped with their text: a stylesheet inside inline SVG
+/// applies to the whole page, while a `style` attribute only reaches its own
+/// element.
+pub fn sanitize_svg(svg: &str) -> String {
+    ammonia::Builder::empty()
+        .add_tags(SVG_TAGS.split_whitespace())
+        .add_generic_attributes(SVG_ATTRIBUTES.split_whitespace())
+        .add_url_schemes(["http", "https", "mailto", "data", "file"])
+        .clean_content_tags(["script", "style"].into())
+        .link_rel(None)
+        .clean(svg)
+        .to_string()
+}
+
+/// Strip everything before the `<svg` element
+fn inline_svg(svg: &str) -> String {
+    match svg.find("<svg") {
+        Some(start) => svg[start..].trim_end().to_string(),
+        None => svg.trim().to_string(),
+    }
+}
+
+/// Quote a string as a JavaScript string literal
+fn js_string(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() || c == '\u{2028}' || c == '\u{2029}' => {
+                quoted.push_str(&format!("\\u{:04x}", c as u32))
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts renders and returns a fixed SVG document
+    struct FakeRenderer<'a> {
+        calls: &'a AtomicUsize,
+    }
+
+    impl DiagramRenderer for FakeRenderer<'_> {
+        fn handles(&self, language: &str) -> bool {
+            language == "fake"
+        }
+
+        fn render(&self, _language: &str, source: &str) -> Result<String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(format!(
+                "<?xml version=\"1.0\"?>\n<!DOCTYPE svg>\n<svg><text>{}</text></svg>\n",
+                source
+            ))
+        }
+
+        fn cache_key(&self) -> String {
+            "fake".to_string()
+        }
+    }
+
+    #[test]
+    fn test_render_is_cached_by_content() {
+        let calls = AtomicUsize::new(0);
+        let mut diagrams = Diagrams::new();
+        diagrams.add(FakeRenderer { calls: &calls });
+
+        let source = "cache test a -> b";
+        let svg = diagrams.render("fake", source).unwrap().unwrap();
+        assert_eq!(svg, "<svg><text>cache test a -&gt; b</text></svg>");
+        diagrams.render("fake", source).unwrap().unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        diagrams
+            .render("fake", "cache test b -> c")
+            .unwrap()
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        assert!(diagrams.render("other", source).is_none());
+    }
+
+    #[test]
+    fn test_command_renderer() {
+        let renderer = CommandRenderer::new("echo", "cat").unwrap();
+        assert!(renderer.handles("ECHO"));
+        assert_eq!(renderer.render("echo", "<svg/>").unwrap(), "<svg/>");
+
+        let missing = CommandRenderer::new("dot", "md2pdf-no-such-command -Tsvg").unwrap();
+        let err = missing.render("dot", "digraph {}").unwrap_err();
+        assert!(err.to_string().contains("md2pdf-no-such-command"));
+
+        assert!(CommandRenderer::new("dot", "  ").is_err());
+    }
+
+    #[test]
+    fn test_mermaid_ids_differ_per_diagram() {
+        fn id(call: &str) -> &str {
+            let start = call.find("md2pdf-mermaid-").unwrap();
+            let end = start + call[start..].find('"').unwrap();
+            &call[start..end]
+        }
+
+        let first = mermaid_call("graph TD\n  A --> B");
+        let second = mermaid_call("graph TD\n  B --> C");
+        assert_ne!(id(&first), id(&second));
+        assert_eq!(id(&first), id(&mermaid_call("graph TD\n  A --> B")));
+    }
+
+    #[test]
+    fn test_sanitize_svg() {
+        let svg = concat!(
+            r##"<svg viewBox="0 0 10 10" onload="alert(1)"><script>alert(2)</script>"##,
+            r##"<style>.node &gt; rect { fill: red; }</style>"##,
+            r##"<a href="javascript:alert(3)"><rect width="4" height="4" onclick="alert(4)"/></a>"##,
+            r##"<foreignObject><div>label</div><img src="x" onerror="alert(5)"></foreignObject>"##,
+            r##"<use href="#marker"/><text x="1" style="fill: blue">a &lt; b</text></svg>"##
+        );
+        let clean = sanitize_svg(svg);
+        assert!(clean.contains(r#"<svg viewBox="0 0 10 10">"#));
+        assert!(clean.contains(r#"<rect width="4" height="4">"#));
+        assert!(clean.contains(r##"<use href="#marker">"##));
+        assert!(clean.contains(r#"<text x="1" style="fill: blue">a &lt; b</text>"#));
+        for unsafe_markup in [
+            "alert",
+            "script",
+            "<style",
+            "rect {",
+            "foreignObject",
+            "<div",
+            "<img",
+        ] {
+            assert!(
+                !clean.contains(unsafe_markup),
+                "{} in {}",
+                unsafe_markup,
+                clean
+            );
+        }
+
+        // Text in a style element must not turn back into markup
+        let clean = sanitize_svg(
+            "<svg><style>&lt;/style&gt;&lt;img src=x onerror=alert(1)&gt;</style></svg>",
+        );
+        assert!(!clean.contains("<img"), "{}", clean);
+    }
+
+    #[test]
+    #[cfg(feature = "bundled-mermaid")]
+    fn test_bundled_mermaid() {
+        let script = BUNDLED_MERMAID.unwrap();
+        assert!(script.contains("globalThis.mermaid"));
+        assert!(!script.contains("</script"));
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "chrome-tests"),
+        ignore = "requires a local Chrome/Chromium installation"
+    )]
+    #[cfg(feature = "bundled-mermaid")]
+    fn test_mermaid_renders_with_bundled_build() {
+        let renderer = Renderer::new();
+        let mermaid = MermaidRenderer::bundled(&renderer).unwrap();
+        let first = mermaid.render("mermaid", "graph TD\n  A --> B").unwrap();
+        let second = mermaid.render("mermaid", "graph TD\n  B --> C").unwrap();
+        assert!(first.contains("<svg"));
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_js_string() {
+        assert_eq!(js_string("a\"b\\c\nd"), r#""a\"b\\c\nd""#);
+        assert_eq!(js_string("\u{2028}"), r#""\u2028""#);
+    }
+}
@@ -23,12 +23,28 @@ pub enum Md2PdfError {
         source: std::io::Error,
     },
 
+    #[error("Failed to write PDF output")]
+    Output(#[source] std::io::Error),
+
     #[error("Invalid file path: {0}")]
     InvalidPath(PathBuf),
 
     #[error("Markdown parsing error: {0}")]
     MarkdownParse(String),
 
+    #[error("Invalid front matter: {0}")]
+    FrontMatter(String),
+
+    #[error("Invalid math on line {line}: {message} in `{formula}`")]
+    Math {
+        line: usize,
+        formula: String,
+        message: String,
+    },
+
+    #[error("Failed to render {language} diagram: {message}")]
+    Diagram { language: String, message: String },
+
     #[error("HTML generation error: {0}")]
     HtmlGeneration(String),
 
@@ -47,14 +63,54 @@ pub enum Md2PdfError {
     #[error("Failed to generate PDF from Chrome: {0}")]
     ChromePdfGeneration(String),
 
+    #[error("Timed out after {timeout:?} waiting for the document to render (pending: {stage})")]
+    RenderTimeout {
+        timeout: std::time::Duration,
+        stage: String,
+    },
+
+    #[error("Blocked requests under the network policy: {0}")]
+    BlockedRequests(String),
+
+    #[error("Invalid PDF configuration: {0}")]
+    InvalidPdfConfig(String),
+
+    #[error("Image not found: {0}")]
+    MissingImage(PathBuf),
+
+    #[error("Unsupported image format: {0}")]
+    UnsupportedImage(PathBuf),
+
+    #[error("Unknown highlight theme: {name} (available: {available})")]
+    UnknownHighlightTheme { name: String, available: String },
+
     #[error("Template error: {0}")]
     Template(String),
 
     #[error("No input files provided")]
     NoInputFiles,
 
+    #[error("Invalid configuration file {path}: {message}")]
+    Config { path: PathBuf, message: String },
+
+    #[error("Invalid book manifest {path}: {message}")]
+    Manifest { path: PathBuf, message: String },
+
+    #[error("Chapter file not found: {chapter} (listed in {manifest})")]
+    MissingChapter { chapter: PathBuf, manifest: PathBuf },
+
     #[error("Input file must have .md extension: {0}")]
     InvalidExtension(PathBuf),
+
+    #[error("Conversion of {path} panicked: {message}")]
+    ConversionPanicked { path: PathBuf, message: String },
+
+    #[error("Failed to start the preview server on {address}")]
+    Server {
+        address: String,
+        #[source]
+        source: std::io::Error,
+    },
 }
 
 /// Type alias for Results using Md2PdfError
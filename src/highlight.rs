@@ -0,0 +1,105 @@
+//! Syntax highlighting for fenced code blocks
+//!
+//! Code is highlighted ahead of rendering using syntect's bundled grammars and
+//! emitted as spans with `hl-` prefixed classes. The chosen theme is turned
+//! into a stylesheet that colours those spans.
+
+use crate::error::{Md2PdfError, Result};
+use log::warn;
+use std::sync::OnceLock;
+use syntect::highlighting::ThemeSet;
+use syntect::html::{css_for_theme_with_class_style, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+/// Theme used when none is chosen
+pub const DEFAULT_THEME: &str = "InspiredGitHub";
+
+/// Class set on highlighted `<pre>` elements, carrying the theme's colours
+pub const CODE_CLASS: &str = "hl-code";
+
+/// Prefix keeping highlight classes apart from the document's own classes
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+fn syntax_set() -> &'static SyntaxSet {
+    static SYNTAXES: OnceLock<SyntaxSet> = OnceLock::new();
+    SYNTAXES.get_or_init(SyntaxSet::load_defaults_newlines)
+}
+
+fn theme_set() -> &'static ThemeSet {
+    static THEMES: OnceLock<ThemeSet> = OnceLock::new();
+    THEMES.get_or_init(ThemeSet::load_defaults)
+}
+
+/// Names of the bundled highlight themes, sorted
+pub fn available_themes() -> Vec<&'static str> {
+    theme_set().themes.keys().map(String::as_str).collect()
+}
+
+/// Generate the stylesheet for a highlight theme
+pub fn theme_css(name: &str) -> Result<String> {
+    let theme = theme_set()
+        .themes
+        .get(name)
+        .ok_or_else(|| Md2PdfError::UnknownHighlightTheme {
+            name: name.to_string(),
+            available: available_themes().join(", "),
+        })?;
+    css_for_theme_with_class_style(theme, CLASS_STYLE)
+        .map_err(|e| Md2PdfError::Template(format!("highlight theme {}: {}", name, e)))
+}
+
+/// Highlight `code` written in `language`
+///
+/// The language is matched against grammar names and file extensions, so
+/// both `rust` and `rs` work. Returns `None` for unknown languages, in which
+/// case the code should be emitted as plain text.
+pub fn highlight_code(code: &str, language: &str) -> Option<String> {
+    let syntaxes = syntax_set();
+    let syntax = syntaxes.find_syntax_by_token(language)?;
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, syntaxes, CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        if let Err(e) = generator.parse_html_for_line_which_includes_newline(line) {
+            warn!("Failed to highlight {} code block: {}", language, e);
+            return None;
+        }
+    }
+    Some(generator.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_known_language() {
+        let html = highlight_code("fn main() {}\n", "rust").unwrap();
+        assert!(html.contains(r#"<span class="hl-"#));
+        assert!(html.contains("main"));
+
+        // File extensions work as language names too
+        assert!(highlight_code("fn main() {}\n", "rs").is_some());
+    }
+
+    #[test]
+    fn test_highlight_escapes_code() {
+        let html = highlight_code("let a = \"<b>\";\n", "rust").unwrap();
+        assert!(!html.contains("<b>"));
+        assert!(html.contains("&lt;b&gt;"));
+    }
+
+    #[test]
+    fn test_highlight_unknown_language() {
+        assert!(highlight_code("whatever", "no-such-language").is_none());
+    }
+
+    #[test]
+    fn test_theme_css() {
+        let css = theme_css(DEFAULT_THEME).unwrap();
+        assert!(css.contains(".hl-code"));
+
+        let err = theme_css("Missing").unwrap_err();
+        assert!(err.to_string().contains(DEFAULT_THEME));
+        assert!(available_themes().contains(&DEFAULT_THEME));
+    }
+}
@@ -3,12 +3,162 @@
 //! This module converts parsed Markdown into well-structured HTML with proper
 //! semantic elements and CSS classes for intelligent page break handling.
 
-use crate::error::Result;
-use pulldown_cmark::{html, Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+use crate::diagram::{self, Diagrams};
+use crate::error::{Md2PdfError, Result};
+use crate::highlight;
+use crate::template::escape_html;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use latex2mathml::{latex_to_mathml, DisplayStyle};
+use log::{debug, warn};
+use pulldown_cmark::{
+    html, CodeBlockKind, CowStr, Event, HeadingLevel, Options, Parser, Tag, TagEnd,
+};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use url::Url;
+
+/// A heading found while generating HTML
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Heading {
+    /// Heading level, 1 for `<h1>` through 6 for `<h6>`
+    pub level: u8,
+    /// Element id, either given in the markdown or generated from the text
+    pub id: String,
+    /// Plain text of the heading
+    pub text: String,
+}
+
+/// Generated HTML together with the document's heading tree
+#[derive(Debug, Clone, Default)]
+pub struct HtmlOutput {
+    /// HTML fragment for the document body
+    pub html: String,
+    /// Headings in document order
+    pub headings: Vec<Heading>,
+    /// Whether a table of contents was inserted
+    pub has_toc: bool,
+}
+
+/// Placeholder left where a `[TOC]` marker stood until headings are known
+const TOC_PLACEHOLDER: &str = "<!-- md2pdf:toc -->";
+
+/// How raw HTML written in the markdown is handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RawHtml {
+    /// Keep allowlisted elements and attributes, dropping scripts, frames,
+    /// event handlers and other active content
+    #[default]
+    Sanitize,
+    /// Pass raw HTML through untouched
+    Allow,
+    /// Show raw HTML as literal text
+    Escape,
+}
+
+impl FromStr for RawHtml {
+    type Err = Md2PdfError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "sanitize" => Ok(RawHtml::Sanitize),
+            "allow" => Ok(RawHtml::Allow),
+            "escape" => Ok(RawHtml::Escape),
+            _ => Err(Md2PdfError::HtmlGeneration(format!(
+                "unknown raw HTML mode '{}' (expected sanitize, allow or escape)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for RawHtml {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RawHtml::Sanitize => write!(f, "sanitize"),
+            RawHtml::Allow => write!(f, "allow"),
+            RawHtml::Escape => write!(f, "escape"),
+        }
+    }
+}
+
+/// Options for the markdown to HTML stage
+#[derive(Debug, Clone)]
+pub struct HtmlOptions<'a> {
+    /// Directory that relative references in the markdown are resolved against
+    pub base_dir: Option<PathBuf>,
+    /// Inline local images as base64 `data:` URIs
+    pub embed_images: bool,
+    /// Fail instead of warning when a referenced image cannot be embedded
+    pub strict: bool,
+    /// Insert a table of contents at the top when there is no `[TOC]` marker
+    pub toc: bool,
+    /// Deepest heading level listed in the table of contents
+    pub toc_depth: u8,
+    /// Syntax highlight fenced code blocks with a known language
+    pub highlight: bool,
+    /// Lines preceding the markdown in its source file, such as front
+    /// matter, added to line numbers in error messages
+    pub line_offset: usize,
+    /// Renderers turning diagram code blocks into inline SVG
+    pub diagrams: Option<&'a Diagrams<'a>>,
+    /// How raw HTML in the markdown is handled
+    pub raw_html: RawHtml,
+    /// Where this markdown sits in a document merged from several files
+    pub chapter: Option<&'a Chapter>,
+}
+
+/// A markdown file's place in a document merged from several files
+///
+/// Heading and footnote ids are prefixed with the chapter id so they stay
+/// unique across chapters, and links to other chapter files become links
+/// within the merged document.
+#[derive(Debug, Clone, Default)]
+pub struct Chapter {
+    /// Element id of the chapter's section
+    pub id: String,
+    /// Ids of all chapters in the document, keyed by the canonical path of
+    /// their markdown file
+    pub ids: HashMap<PathBuf, String>,
+}
+
+impl Chapter {
+    /// Prefix for ids defined in this chapter
+    fn id_prefix(&self) -> String {
+        format!("{}--", self.id)
+    }
+}
+
+impl Default for HtmlOptions<'_> {
+    fn default() -> Self {
+        Self {
+            base_dir: None,
+            embed_images: false,
+            strict: false,
+            toc: false,
+            toc_depth: 3,
+            highlight: false,
+            line_offset: 0,
+            diagrams: None,
+            raw_html: RawHtml::Sanitize,
+            chapter: None,
+        }
+    }
+}
 
 /// Convert markdown string to HTML with semantic markup
 pub fn markdown_to_html(markdown: &str) -> Result<String> {
-    // Enable all markdown extensions for maximum compatibility
+    markdown_to_html_with(markdown, &HtmlOptions::default()).map(|output| output.html)
+}
+
+/// Parser options enabling every markdown extension md2pdf supports
+fn parser_options() -> Options {
     let mut options = Options::empty();
     options.insert(Options::ENABLE_TABLES);
     options.insert(Options::ENABLE_FOOTNOTES);
@@ -16,31 +166,558 @@ pub fn markdown_to_html(markdown: &str) -> Result<String> {
     options.insert(Options::ENABLE_TASKLISTS);
     options.insert(Options::ENABLE_SMART_PUNCTUATION);
     options.insert(Options::ENABLE_HEADING_ATTRIBUTES);
+    options.insert(Options::ENABLE_MATH);
+    options
+}
+
+/// Convert markdown string to HTML using the given options
+///
+/// Every heading gets an `id` (kept from `{#custom-id}` attributes or derived
+/// from its text) and is reported in [`HtmlOutput::headings`]. A paragraph
+/// holding only `[TOC]` is replaced by a table of contents.
+pub fn markdown_to_html_with(markdown: &str, html_options: &HtmlOptions) -> Result<HtmlOutput> {
+    // Raw HTML from the document is escaped here, or tagged for sanitizing
+    let parser = Parser::new_ext(markdown, parser_options())
+        .into_offset_iter()
+        .map(|(event, range)| (handle_raw_html(event, html_options.raw_html), range));
+
+    // Render formulas first, while source offsets are available for errors
+    let events = render_math(parser, markdown, html_options.line_offset)?;
+
+    // Inline local images so the HTML is self-contained
+    let events = if html_options.embed_images {
+        embed_local_images(events.into_iter(), html_options)?
+    } else {
+        events
+    };
 
-    let parser = Parser::new_ext(markdown, options);
+    // Point links and images of a chapter into the merged document
+    let events = match html_options.chapter {
+        Some(chapter) => rewrite_chapter_links(events, chapter, html_options)?,
+        None => events,
+    };
+
+    // Mark where the table of contents goes
+    let (events, has_marker) = replace_toc_marker(events);
 
     // Add semantic wrappers and page break hints
-    let parser = add_page_break_hints(parser);
+    let id_prefix = html_options
+        .chapter
+        .map(Chapter::id_prefix)
+        .unwrap_or_default();
+    let (events, headings) = add_page_break_hints(events.into_iter(), &id_prefix);
+
+    // Lay out code blocks, with diagrams, syntax highlighting and line numbers
+    let events = render_code_blocks(events, html_options)?;
 
     // Convert to HTML
     let mut html_output = String::new();
-    html::push_html(&mut html_output, parser);
+    if html_options.raw_html == RawHtml::Sanitize {
+        html_output = render_sanitized(events);
+    } else {
+        html::push_html(&mut html_output, events.into_iter());
+    }
+
+    let has_toc = has_marker || html_options.toc;
+    if has_toc {
+        let toc = table_of_contents(&headings, html_options.toc_depth);
+        if has_marker {
+            html_output = html_output.replace(TOC_PLACEHOLDER, &toc);
+        } else {
+            html_output.insert_str(0, &toc);
+        }
+    }
+
+    Ok(HtmlOutput {
+        html: html_output,
+        headings,
+        has_toc,
+    })
+}
+
+/// Prefix tagging raw HTML events from the document until they are sanitized
+const RAW_HTML_MARKER: &str = "\u{E000}md2pdf-raw\u{E001}";
+
+/// Apply the raw HTML mode to a parser `Html`/`InlineHtml` event
+fn handle_raw_html(event: Event<'_>, mode: RawHtml) -> Event<'_> {
+    match (event, mode) {
+        (Event::Html(html) | Event::InlineHtml(html), RawHtml::Escape) => Event::Text(html),
+        (Event::Html(html), RawHtml::Sanitize) => {
+            Event::Html(format!("{}{}", RAW_HTML_MARKER, html).into())
+        }
+        (Event::InlineHtml(html), RawHtml::Sanitize) => {
+            Event::InlineHtml(format!("{}{}", RAW_HTML_MARKER, html).into())
+        }
+        (event, _) => event,
+    }
+}
+
+/// Render `$...$` and `$$...$$` formulas to MathML
+///
+/// Display math is wrapped in a `math-display` block. A formula that fails
+/// to parse is reported with its line in the source file. The converter
+/// copies parts of the formula into its output unescaped, so the MathML goes
+/// through [`sanitize_mathml`].
+fn render_math<'a>(
+    events: impl Iterator<Item = (Event<'a>, Range<usize>)>,
+    markdown: &str,
+    line_offset: usize,
+) -> Result<Vec<Event<'a>>> {
+    events
+        .map(|(event, range)| {
+            let (latex, style) = match &event {
+                Event::InlineMath(latex) => (latex, DisplayStyle::Inline),
+                Event::DisplayMath(latex) => (latex, DisplayStyle::Block),
+                _ => return Ok(event),
+            };
+            let mathml = latex_to_mathml(latex, style).map_err(|e| Md2PdfError::Math {
+                line: line_offset + markdown[..range.start].matches('\n').count() + 1,
+                formula: latex.to_string(),
+                message: e.to_string(),
+            })?;
+            let mathml = sanitize_mathml(&mathml);
+            Ok(match style {
+                DisplayStyle::Inline => Event::InlineHtml(mathml.into()),
+                DisplayStyle::Block => Event::InlineHtml(
+                    format!(r#"<span class="math-display no-break">{}</span>"#, mathml).into(),
+                ),
+            })
+        })
+        .collect()
+}
+
+/// Code blocks longer than this may split across pages
+const LONG_CODE_BLOCK_LINES: usize = 30;
+
+/// Attributes parsed from a code fence's info string
+///
+/// Written after the language in braces, e.g.
+/// ```` ```rust {linenos=true hl_lines="3-5 8" start=10} ````.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeBlockInfo {
+    /// Language name, the first word of the info string
+    pub language: Option<String>,
+    /// Show a line number gutter
+    pub line_numbers: bool,
+    /// Lines to highlight, counted from 1 at the top of the block
+    pub highlight_lines: Vec<(usize, usize)>,
+    /// Number shown for the first line
+    pub start: usize,
+}
+
+impl Default for CodeBlockInfo {
+    fn default() -> Self {
+        Self {
+            language: None,
+            line_numbers: false,
+            highlight_lines: Vec::new(),
+            start: 1,
+        }
+    }
+}
+
+impl CodeBlockInfo {
+    /// Parse a fence info string, warning about attributes it can't use
+    pub fn parse(info: &str) -> Self {
+        let (language, attributes) = match info.find('{') {
+            Some(index) => (&info[..index], Some(&info[index + 1..])),
+            None => (info, None),
+        };
+        let mut parsed = Self {
+            language: language.split_whitespace().next().map(str::to_string),
+            ..Default::default()
+        };
+
+        let Some(attributes) = attributes else {
+            return parsed;
+        };
+        let attributes = attributes.trim_end().trim_end_matches('}');
+        for (key, value) in code_block_attributes(attributes) {
+            let valid = match key.as_str() {
+                "linenos" => match value.as_str() {
+                    "true" | "table" | "inline" => {
+                        parsed.line_numbers = true;
+                        true
+                    }
+                    "false" => {
+                        parsed.line_numbers = false;
+                        true
+                    }
+                    _ => false,
+                },
+                "hl_lines" => match parse_line_ranges(&value) {
+                    Some(ranges) => {
+                        parsed.highlight_lines = ranges;
+                        true
+                    }
+                    None => false,
+                },
+                "start" | "linenostart" => match value.parse() {
+                    Ok(start) => {
+                        parsed.start = start;
+                        true
+                    }
+                    Err(_) => false,
+                },
+                _ => {
+                    warn!("Ignoring unknown code block attribute: {}", key);
+                    continue;
+                }
+            };
+            if !valid {
+                warn!("Ignoring invalid code block attribute: {}={}", key, value);
+            }
+        }
+
+        parsed
+    }
+
+    /// Whether the block needs the line-by-line listing layout
+    fn is_listing(&self) -> bool {
+        self.line_numbers || !self.highlight_lines.is_empty()
+    }
+
+    fn is_highlighted(&self, line: usize) -> bool {
+        self.highlight_lines
+            .iter()
+            .any(|&(first, last)| (first..=last).contains(&line))
+    }
+}
+
+/// Split `key=value` pairs separated by whitespace or commas, where values
+/// may be double quoted
+fn code_block_attributes(attributes: &str) -> Vec<(String, String)> {
+    let mut pairs = Vec::new();
+    let mut chars = attributes.chars().peekable();
+
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace() || *c == ',') {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| *c != '=' && !c.is_whitespace() && *c != ',') {
+            key.push(c);
+        }
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'=').is_some() {
+            if chars.next_if_eq(&'"').is_some() {
+                for c in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                    value.push(c);
+                }
+            } else {
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != ',') {
+                    value.push(c);
+                }
+            }
+        } else {
+            // A bare flag such as `{linenos}`
+            value.push_str("true");
+        }
+        pairs.push((key, value));
+    }
+
+    pairs
+}
+
+/// Parse line ranges like `3-5 8` or `3-5,8`
+fn parse_line_ranges(value: &str) -> Option<Vec<(usize, usize)>> {
+    value
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let (first, last) = part.split_once('-').unwrap_or((part, part));
+            let (first, last) = (first.trim().parse().ok()?, last.trim().parse().ok()?);
+            (first <= last).then_some((first, last))
+        })
+        .collect()
+}
+
+/// Render code blocks, highlighting those in a known language
+///
+/// Blocks in a diagram language handled by [`HtmlOptions::diagrams`] become
+/// inline SVG figures; if rendering fails the block is kept as code, or in
+/// strict mode the error is returned. Short blocks are kept on one page.
+/// Blocks with line numbers or highlighted lines, and blocks longer than
+/// [`LONG_CODE_BLOCK_LINES`], are laid out one table row per line so they can
+/// split between lines, with a "continued" header repeated on every page
+/// after the first.
+fn render_code_blocks<'a>(
+    events: Vec<Event<'a>>,
+    html_options: &HtmlOptions,
+) -> Result<Vec<Event<'a>>> {
+    let mut output = Vec::with_capacity(events.len());
+    let mut events = events.into_iter();
+
+    while let Some(event) = events.next() {
+        let info = match &event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) => CodeBlockInfo::parse(info),
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)) => CodeBlockInfo::default(),
+            _ => {
+                output.push(event);
+                continue;
+            }
+        };
+
+        let mut code = String::new();
+        for inner in events.by_ref() {
+            match inner {
+                Event::Text(text) => code.push_str(&text),
+                Event::End(TagEnd::CodeBlock) => break,
+                _ => {}
+            }
+        }
+
+        if let (Some(language), Some(diagrams)) = (&info.language, html_options.diagrams) {
+            match diagrams.render(language, &code) {
+                Some(Ok(svg)) => {
+                    output.push(Event::Html(diagram::diagram_figure(language, &svg).into()));
+                    continue;
+                }
+                Some(Err(e)) if html_options.strict => return Err(e),
+                Some(Err(e)) => warn!("{}; keeping the code block", e),
+                None => {}
+            }
+        }
+
+        let highlighted = match (&info.language, html_options.highlight) {
+            (Some(language), true) => {
+                let highlighted = highlight::highlight_code(&code, language);
+                if highlighted.is_none() {
+                    debug!("No syntax highlighting for language: {}", language);
+                }
+                highlighted
+            }
+            _ => None,
+        };
+
+        let line_count = code.lines().count();
+        let html = if info.is_listing() || line_count > LONG_CODE_BLOCK_LINES {
+            code_listing(&info, &code, highlighted.as_deref())
+        } else {
+            code_block(&info, &code, highlighted.as_deref())
+        };
+        output.push(Event::Html(html.into()));
+    }
+
+    Ok(output)
+}
+
+/// Opening `<code>` tag, with the language class if there is one
+fn code_open_tag(info: &CodeBlockInfo) -> String {
+    match &info.language {
+        Some(language) => format!(r#"<code class="language-{}">"#, escape_html(language)),
+        None => "<code>".to_string(),
+    }
+}
+
+/// A code block kept together on one page
+fn code_block(info: &CodeBlockInfo, code: &str, highlighted: Option<&str>) -> String {
+    let pre = match highlighted {
+        Some(_) => format!(r#"<pre class="{}">"#, highlight::CODE_CLASS),
+        None => "<pre>".to_string(),
+    };
+    let body = match highlighted {
+        Some(html) => html.to_string(),
+        None => escape_html(code),
+    };
+    format!(
+        "<div class=\"code-wrapper no-break\">{}{}{}</code></pre>\n</div>\n",
+        pre,
+        code_open_tag(info),
+        body
+    )
+}
+
+/// A code block laid out one line per table row
+fn code_listing(info: &CodeBlockInfo, code: &str, highlighted: Option<&str>) -> String {
+    let lines = match highlighted {
+        Some(html) => split_html_lines(html),
+        None => code.lines().map(escape_html).collect(),
+    };
+    let label = info.language.as_deref().unwrap_or("Code");
+    let columns = if info.line_numbers { 2 } else { 1 };
+    let table_class = match highlighted {
+        Some(_) => format!("code-listing-table {}", highlight::CODE_CLASS),
+        None => "code-listing-table".to_string(),
+    };
+
+    let mut html = format!(
+        r#"<div class="code-wrapper code-listing">
+<div class="code-listing-label">{label}</div>
+<table class="{table_class}">
+<thead><tr><th class="code-continued" colspan="{columns}">{label} (continued)</th></tr></thead>
+<tbody>
+"#,
+        label = escape_html(label),
+        table_class = table_class,
+        columns = columns,
+    );
+
+    let open = code_open_tag(info);
+    for (index, line) in lines.iter().enumerate() {
+        let class = if info.is_highlighted(index + 1) {
+            "code-line code-line-highlighted"
+        } else {
+            "code-line"
+        };
+        html.push_str(&format!(r#"<tr class="{}">"#, class));
+        if info.line_numbers {
+            html.push_str(&format!(
+                r#"<td class="line-number">{}</td>"#,
+                info.start + index
+            ));
+        }
+        html.push_str(&format!(
+            "<td class=\"line-code\">{}{}</code></td></tr>\n",
+            open, line
+        ));
+    }
+
+    html.push_str("</tbody>\n</table>\n</div>\n");
+    html
+}
+
+/// Split highlighted HTML into lines, closing the spans open at the end of
+/// each line and reopening them at the start of the next
+fn split_html_lines(html: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut open_tags: Vec<&str> = Vec::new();
+    let mut line = String::new();
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        if rest.starts_with('<') {
+            let end = rest.find('>').map_or(rest.len(), |index| index + 1);
+            let tag = &rest[..end];
+            if tag.starts_with("</") {
+                open_tags.pop();
+            } else if !tag.ends_with("/>") {
+                open_tags.push(tag);
+            }
+            line.push_str(tag);
+            rest = &rest[end..];
+        } else if let Some(text) = rest.strip_prefix('\n') {
+            line.push_str(&"</span>".repeat(open_tags.len()));
+            lines.push(std::mem::replace(&mut line, open_tags.concat()));
+            rest = text;
+        } else {
+            let end = rest.find(['<', '\n']).unwrap_or(rest.len());
+            line.push_str(&rest[..end]);
+            rest = &rest[end..];
+        }
+    }
+
+    // Keep a trailing line unless it is only reopened spans
+    if line != open_tags.concat() {
+        line.push_str(&"</span>".repeat(open_tags.len()));
+        lines.push(line);
+    }
+    lines
+}
+
+/// Replace paragraphs consisting of just `[TOC]` with a placeholder
+fn replace_toc_marker(events: Vec<Event<'_>>) -> (Vec<Event<'_>>, bool) {
+    let mut output = Vec::with_capacity(events.len());
+    let mut found = false;
+    let mut index = 0;
+
+    while index < events.len() {
+        if let Event::Start(Tag::Paragraph) = events[index] {
+            // The marker may arrive split into several text events
+            let mut text = String::new();
+            let mut end = index + 1;
+            while let Some(Event::Text(chunk)) = events.get(end) {
+                text.push_str(chunk);
+                end += 1;
+            }
+            if text.trim() == "[TOC]"
+                && matches!(events.get(end), Some(Event::End(TagEnd::Paragraph)))
+            {
+                output.push(Event::Html(TOC_PLACEHOLDER.into()));
+                found = true;
+                index = end + 1;
+                continue;
+            }
+        }
+        output.push(events[index].clone());
+        index += 1;
+    }
 
-    Ok(html_output)
+    (output, found)
+}
+
+/// Build the table of contents for headings up to `max_depth`
+///
+/// Each entry links to its heading and carries an empty page number slot
+/// that [`fill_toc_page_numbers`] fills in once the PDF has been laid out.
+pub fn table_of_contents(headings: &[Heading], max_depth: u8) -> String {
+    let entries: String = headings
+        .iter()
+        .filter(|heading| heading.level <= max_depth)
+        .map(|heading| {
+            let id = escape_html(&heading.id);
+            format!(
+                r##"<li class="toc-level-{level}"><a href="#{id}"><span class="toc-text">{text}</span><span class="toc-leader"></span><span class="toc-page" data-toc-target="{id}"></span></a></li>
+"##,
+                level = heading.level,
+                id = id,
+                text = escape_html(&heading.text),
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<nav class="toc">
+<p class="toc-title">Contents</p>
+<ul class="toc-list">
+{}</ul>
+</nav>
+"#,
+        entries
+    )
+}
+
+/// Fill table of contents page numbers, keyed by heading id
+pub fn fill_toc_page_numbers(html: &str, pages: &HashMap<String, u32>) -> String {
+    let mut filled = html.to_string();
+    for (id, page) in pages {
+        let empty = format!(
+            r#"<span class="toc-page" data-toc-target="{}"></span>"#,
+            escape_html(id)
+        );
+        let numbered = format!(
+            r#"<span class="toc-page" data-toc-target="{}">{}</span>"#,
+            escape_html(id),
+            page
+        );
+        filled = filled.replace(&empty, &numbered);
+    }
+    filled
 }
 
 /// Add page break hints to prevent content splitting
 ///
 /// This function wraps certain elements with CSS classes that indicate
-/// they should not be split across pages.
+/// they should not be split across pages. It also collects the headings,
+/// giving each one a unique id that starts with `id_prefix`.
 fn add_page_break_hints<'a>(
     parser: impl Iterator<Item = Event<'a>>,
-) -> impl Iterator<Item = Event<'a>> {
+    id_prefix: &str,
+) -> (Vec<Event<'a>>, Vec<Heading>) {
     let mut events = Vec::new();
     let mut _in_table = false;
-    let mut _in_code_block = false;
-    let mut _in_heading = false;
-    let mut _heading_level = HeadingLevel::H1;
+    let mut headings = Vec::new();
+    let mut used_ids = HashSet::new();
+    // Index of the open heading's start event and the text seen so far
+    let mut open_heading: Option<(usize, String)> = None;
 
     for event in parser {
         match &event {
@@ -56,23 +733,40 @@ fn add_page_break_hints<'a>(
                 events.push(event);
                 events.push(Event::Html(r#"</div>"#.into()));
             }
-            Event::Start(Tag::CodeBlock(_)) => {
-                _in_code_block = true;
-                events.push(Event::Html(r#"<div class="code-wrapper no-break">"#.into()));
+            Event::Start(Tag::Heading { .. }) => {
+                open_heading = Some((events.len(), String::new()));
                 events.push(event);
             }
-            Event::End(TagEnd::CodeBlock) => {
-                _in_code_block = false;
+            Event::End(TagEnd::Heading(level)) => {
+                if let Some((start, text)) = open_heading.take() {
+                    let text = text.trim().to_string();
+                    if let Event::Start(Tag::Heading { id, .. }) = &mut events[start] {
+                        let heading_id = match id {
+                            Some(existing) => existing.to_string(),
+                            None => unique_slug(&text, &mut used_ids),
+                        };
+                        used_ids.insert(heading_id.clone());
+                        let heading_id = format!("{}{}", id_prefix, heading_id);
+                        *id = Some(heading_id.clone().into());
+                        headings.push(Heading {
+                            level: heading_level_number(*level),
+                            id: heading_id,
+                            text,
+                        });
+                    }
+                }
                 events.push(event);
-                events.push(Event::Html(r#"</div>"#.into()));
             }
-            Event::Start(Tag::Heading { level, .. }) => {
-                _in_heading = true;
-                _heading_level = *level;
+            Event::Text(text) | Event::Code(text) if open_heading.is_some() => {
+                if let Some((_, heading_text)) = &mut open_heading {
+                    heading_text.push_str(text);
+                }
                 events.push(event);
             }
-            Event::End(TagEnd::Heading(_)) => {
-                _in_heading = false;
+            Event::SoftBreak | Event::HardBreak if open_heading.is_some() => {
+                if let Some((_, heading_text)) = &mut open_heading {
+                    heading_text.push(' ');
+                }
                 events.push(event);
             }
             Event::Start(Tag::BlockQuote(_)) => {
@@ -87,16 +781,385 @@ fn add_page_break_hints<'a>(
         }
     }
 
-    events.into_iter()
+    (events, headings)
+}
+
+/// Numeric level of a heading
+fn heading_level_number(level: HeadingLevel) -> u8 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+/// Turn heading text into a URL fragment such as `getting-started`
+///
+/// Letters and digits are lowercased and kept, whitespace, `-` and `_` become
+/// single hyphens, and everything else is dropped.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    slug.trim_matches('-').to_string()
+}
+
+/// Slug for `text` that is not in `used`, suffixed with `-1`, `-2`, ... if needed
+pub(crate) fn unique_slug(text: &str, used: &mut HashSet<String>) -> String {
+    let base = match slugify(text) {
+        slug if slug.is_empty() => "section".to_string(),
+        slug => slug,
+    };
+    let mut candidate = base.clone();
+    let mut counter = 1;
+    while used.contains(&candidate) {
+        candidate = format!("{}-{}", base, counter);
+        counter += 1;
+    }
+    candidate
+}
+
+/// Hidden same-document links to every heading
+///
+/// Chrome only emits PDF named destinations for elements that are the target
+/// of a link in the document; these links make every heading addressable so
+/// bookmarks can point at it. The links are clipped to an invisible 1px box
+/// rather than `display: none`, since Chrome skips links that aren't laid
+/// out.
+pub fn heading_anchor_links(headings: &[Heading]) -> String {
+    if headings.is_empty() {
+        return String::new();
+    }
+    let links: String = headings
+        .iter()
+        .map(|heading| format!(r##"<a href="#{}"></a>"##, escape_html(&heading.id)))
+        .collect();
+    format!(
+        concat!(
+            r#"<nav class="md2pdf-anchors" aria-hidden="true" style="position: absolute; "#,
+            r#"width: 1px; height: 1px; overflow: hidden; clip-path: inset(50%)">{}</nav>"#
+        ),
+        links
+    )
+}
+
+/// List the local files referenced as images in markdown
+///
+/// References resolve against `base_dir` like they do when images are
+/// embedded; remote images are skipped. Files are listed whether or not they
+/// exist, each once, in document order.
+pub fn local_images(markdown: &str, base_dir: &Path) -> Result<Vec<PathBuf>> {
+    let base_url = directory_url(base_dir)?;
+    let mut images = Vec::new();
+    for event in Parser::new_ext(markdown, parser_options()) {
+        if let Event::Start(Tag::Image { dest_url, .. }) = event {
+            if let Some(path) = local_image_path(&base_url, &dest_url) {
+                if !images.contains(&path) {
+                    images.push(path);
+                }
+            }
+        }
+    }
+    Ok(images)
 }
 
-/// Sanitize HTML to prevent XSS (basic implementation)
+/// Replace local image references with base64 `data:` URIs
 ///
-/// For production use, consider using a dedicated sanitization library like ammonia
+/// Remote images (`http:`, `https:`, `data:` and other non-file URLs) are
+/// left untouched. Local images that cannot be read produce a warning, or an
+/// error when `strict` is set.
+fn embed_local_images<'a>(
+    parser: impl Iterator<Item = Event<'a>>,
+    html_options: &HtmlOptions,
+) -> Result<Vec<Event<'a>>> {
+    let base_url = match &html_options.base_dir {
+        Some(dir) => directory_url(dir)?,
+        None => directory_url(Path::new("."))?,
+    };
+
+    let mut events = Vec::new();
+    for event in parser {
+        match event {
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => {
+                let dest_url = match local_image_path(&base_url, &dest_url) {
+                    Some(path) => match image_data_uri(&path) {
+                        Ok(data_uri) => {
+                            debug!("Embedded image: {}", path.display());
+                            CowStr::from(data_uri)
+                        }
+                        Err(e) if html_options.strict => return Err(e),
+                        Err(e) => {
+                            warn!("{}; leaving the reference as is", e);
+                            dest_url
+                        }
+                    },
+                    None => dest_url,
+                };
+                events.push(Event::Start(Tag::Image {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                }));
+            }
+            _ => events.push(event),
+        }
+    }
+
+    Ok(events)
+}
+
+/// Rewrite the links and images of one chapter of a merged document
+///
+/// Fragment links (`#intro`) and footnotes get the chapter's id prefix, links
+/// to other chapter files (`chapter2.md#setup`) become links to that chapter's
+/// anchors, and remaining relative references become absolute `file://` URLs
+/// so they still resolve from the merged document.
+fn rewrite_chapter_links<'a>(
+    events: Vec<Event<'a>>,
+    chapter: &Chapter,
+    html_options: &HtmlOptions,
+) -> Result<Vec<Event<'a>>> {
+    let base_url = match &html_options.base_dir {
+        Some(dir) => directory_url(dir)?,
+        None => directory_url(Path::new("."))?,
+    };
+    let prefix = chapter.id_prefix();
+
+    let rewrite = |dest_url: CowStr<'a>| -> CowStr<'a> {
+        if let Some(fragment) = dest_url.strip_prefix('#') {
+            return format!("#{}{}", prefix, fragment).into();
+        }
+        // Absolute URLs such as `https:` and `mailto:` are left alone
+        if Url::parse(&dest_url).is_ok() {
+            return dest_url;
+        }
+        let Ok(url) = base_url.join(&dest_url) else {
+            return dest_url;
+        };
+        let target = url
+            .to_file_path()
+            .ok()
+            .and_then(|path| path.canonicalize().ok())
+            .and_then(|path| chapter.ids.get(&path));
+        // The fragment as written, not percent-encoded like `url.fragment()`
+        let fragment = dest_url.split_once('#').map(|(_, fragment)| fragment);
+        match (target, fragment) {
+            (Some(id), Some(fragment)) if !fragment.is_empty() => {
+                format!("#{}--{}", id, fragment).into()
+            }
+            (Some(id), _) => format!("#{}", id).into(),
+            (None, _) => url.to_string().into(),
+        }
+    };
+
+    Ok(events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => Event::Start(Tag::Link {
+                link_type,
+                dest_url: rewrite(dest_url),
+                title,
+                id,
+            }),
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => Event::Start(Tag::Image {
+                link_type,
+                dest_url: rewrite(dest_url),
+                title,
+                id,
+            }),
+            Event::FootnoteReference(label) => {
+                Event::FootnoteReference(format!("{}{}", prefix, label).into())
+            }
+            Event::Start(Tag::FootnoteDefinition(label)) => Event::Start(Tag::FootnoteDefinition(
+                format!("{}{}", prefix, label).into(),
+            )),
+            event => event,
+        })
+        .collect())
+}
+
+/// `file://` URL of a directory, with a trailing slash
+///
+/// An empty path (the parent of a bare file name) means the current directory.
+pub(crate) fn directory_url(dir: &Path) -> Result<Url> {
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let absolute = dir
+        .canonicalize()
+        .map_err(|_| Md2PdfError::InvalidPath(dir.to_path_buf()))?;
+    Url::from_directory_path(&absolute).map_err(|_| Md2PdfError::InvalidPath(absolute))
+}
+
+/// Resolve an image reference to a local file, if it points to one
+fn local_image_path(base_url: &Url, dest_url: &str) -> Option<PathBuf> {
+    let url = base_url.join(dest_url).ok()?;
+    if url.scheme() == "file" {
+        url.to_file_path().ok()
+    } else {
+        None
+    }
+}
+
+/// Read an image file and encode it as a `data:` URI
+fn image_data_uri(path: &Path) -> Result<String> {
+    if !path.is_file() {
+        return Err(Md2PdfError::MissingImage(path.to_path_buf()));
+    }
+
+    let bytes = fs::read(path).map_err(|e| Md2PdfError::FileRead {
+        path: path.to_path_buf(),
+        source: e,
+    })?;
+
+    let mime = sniff_image_mime(&bytes, path)
+        .ok_or_else(|| Md2PdfError::UnsupportedImage(path.to_path_buf()))?;
+
+    Ok(format!("data:{};base64,{}", mime, BASE64.encode(&bytes)))
+}
+
+/// Detect an image MIME type from its magic bytes, falling back to the extension
+fn sniff_image_mime(bytes: &[u8], path: &Path) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some("image/png");
+    }
+    if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        return Some("image/jpeg");
+    }
+    if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        return Some("image/gif");
+    }
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+
+    let head = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]).to_ascii_lowercase();
+    if head.contains("<svg") {
+        return Some("image/svg+xml");
+    }
+
+    match path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("png") => Some("image/png"),
+        Some("jpg") | Some("jpeg") => Some("image/jpeg"),
+        Some("gif") => Some("image/gif"),
+        Some("webp") => Some("image/webp"),
+        Some("svg") => Some("image/svg+xml"),
+        _ => None,
+    }
+}
+
+/// Sanitize HTML against an allowlist of elements and attributes
+///
+/// Keeps the markup markdown produces plus common formatting elements,
+/// `class`, `id` and `style` attributes, and relative, web, `file:` and
+/// `data:` URLs. Scripts, frames, forms, event handler attributes and
+/// `javascript:` URLs are removed.
 pub fn sanitize_html(html: &str) -> String {
-    // Basic sanitization - in production, use a proper library
-    // For now, we trust the markdown parser's output
-    html.to_string()
+    ammonia::Builder::default()
+        .add_tags(["input", "section"])
+        .add_tag_attributes("input", ["type", "checked", "disabled"])
+        .add_tag_attributes("th", ["align"])
+        .add_tag_attributes("td", ["align"])
+        .add_tag_attributes("a", ["title"])
+        .add_tag_attributes("img", ["title"])
+        .add_generic_attributes(["class", "id", "style"])
+        .add_url_schemes(["data", "file"])
+        .link_rel(None)
+        .clean(html)
+        .to_string()
+}
+
+/// MathML elements kept in rendered formulas, separated by whitespace
+const MATHML_TAGS: &str = "\
+    math mi mn mo ms mspace mtext mrow mfrac msqrt mroot mstyle merror mpadded \
+    mphantom menclose msub msup msubsup munder mover munderover mmultiscripts \
+    mprescripts none mtable mtr mtd mlabeledtr semantics annotation";
+
+/// Attributes kept on any MathML element, separated by whitespace
+const MATHML_ATTRIBUTES: &str = "\
+    display mathvariant displaystyle scriptlevel stretchy fence separator \
+    lspace rspace accent accentunder movablelimits largeop symmetric minsize \
+    maxsize linethickness notation columnalign rowalign columnspacing \
+    rowspacing columnlines rowlines frame width height depth voffset align form \
+    mathcolor mathbackground mathsize encoding xmlns";
+
+/// Sanitize MathML against [`MATHML_TAGS`] and [`MATHML_ATTRIBUTES`]
+///
+/// Text is re-escaped, so a `<` the converter copied from the formula can't
+/// start an element.
+pub fn sanitize_mathml(mathml: &str) -> String {
+    ammonia::Builder::empty()
+        .add_tags(MATHML_TAGS.split_whitespace())
+        .add_generic_attributes(MATHML_ATTRIBUTES.split_whitespace())
+        .clean(mathml)
+        .to_string()
+}
+
+/// Sanitize the HTML rendered from `events`, leaving markup md2pdf itself
+/// generated untouched
+///
+/// Raw HTML from the document was tagged with [`RAW_HTML_MARKER`] by
+/// [`handle_raw_html`]; `Html` events without the marker were added by later
+/// passes and are trusted. They are swapped for placeholders the sanitizer
+/// passes through as text, then restored.
+fn render_sanitized(events: Vec<Event<'_>>) -> String {
+    let nonce = RandomState::new().build_hasher().finish();
+    let mut fragments = Vec::new();
+
+    let events = events.into_iter().map(|event| match event {
+        Event::Html(fragment) | Event::InlineHtml(fragment) => {
+            match fragment.strip_prefix(RAW_HTML_MARKER) {
+                Some(raw) => Event::Html(raw.to_string().into()),
+                None => {
+                    let placeholder = format!("\u{E000}{:x}-{}\u{E001}", nonce, fragments.len());
+                    fragments.push(fragment.into_string());
+                    Event::Text(placeholder.into())
+                }
+            }
+        }
+        event => event,
+    });
+
+    let mut rendered = String::new();
+    html::push_html(&mut rendered, events);
+    let mut sanitized = sanitize_html(&rendered);
+
+    for (index, fragment) in fragments.iter().enumerate() {
+        let placeholder = format!("\u{E000}{:x}-{}\u{E001}", nonce, index);
+        sanitized = sanitized.replacen(&placeholder, fragment, 1);
+    }
+    sanitized
 }
 
 #[cfg(test)]
@@ -107,7 +1170,7 @@ mod tests {
     fn test_markdown_to_html_basic() {
         let markdown = "# Hello\n\nWorld";
         let html = markdown_to_html(markdown).unwrap();
-        assert!(html.contains("<h1>"));
+        assert!(html.contains(r#"<h1 id="hello">"#));
         assert!(html.contains("Hello"));
         assert!(html.contains("<p>"));
         assert!(html.contains("World"));
@@ -162,4 +1225,424 @@ fn main() {
         let html = markdown_to_html(markdown).unwrap();
         assert!(html.contains("checkbox") || html.contains("<li>"));
     }
+
+    #[test]
+    fn test_markdown_to_html_collects_headings() {
+        let markdown = "# Getting Started\n\n## Install `md2pdf`\n\n## Getting Started\n\n### Custom {#my-id}\n\n#### ???";
+        let output = markdown_to_html_with(markdown, &HtmlOptions::default()).unwrap();
+
+        let ids: Vec<_> = output.headings.iter().map(|h| h.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            vec![
+                "getting-started",
+                "install-md2pdf",
+                "getting-started-1",
+                "my-id",
+                "section"
+            ]
+        );
+        let levels: Vec<_> = output.headings.iter().map(|h| h.level).collect();
+        assert_eq!(levels, vec![1, 2, 2, 3, 4]);
+        assert_eq!(output.headings[1].text, "Install md2pdf");
+        assert!(output.html.contains(r#"<h2 id="getting-started-1">"#));
+        assert!(output.html.contains(r#"<h3 id="my-id">"#));
+    }
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Hello, World!"), "hello-world");
+        assert_eq!(slugify("  API -- v2_beta "), "api-v2-beta");
+        assert_eq!(slugify("Über Größe"), "über-größe");
+        assert_eq!(slugify("!!!"), "");
+    }
+
+    #[test]
+    fn test_heading_anchor_links() {
+        let headings = vec![Heading {
+            level: 1,
+            id: "intro".to_string(),
+            text: "Intro".to_string(),
+        }];
+        let links = heading_anchor_links(&headings);
+        assert!(links.contains(r##"<a href="#intro"></a>"##));
+        assert!(links.contains("clip-path: inset(50%)"));
+        assert!(!links.contains("display: none"));
+        assert_eq!(heading_anchor_links(&[]), "");
+    }
+
+    #[test]
+    fn test_toc_marker_is_replaced() {
+        let markdown =
+            "# Title\n\n[TOC]\n\n## First\n\n### Deep\n\n#### Too deep\n\nNot [TOC] here";
+        let output = markdown_to_html_with(markdown, &HtmlOptions::default()).unwrap();
+
+        assert!(output.has_toc);
+        assert!(!output.html.contains("<p>[TOC]</p>"));
+        assert!(output.html.contains("Not [TOC] here"));
+        assert!(output.html.contains(r##"<a href="#first">"##));
+        assert!(output.html.contains(r#"class="toc-level-3""#));
+        assert!(!output.html.contains(r##"href="#too-deep""##));
+
+        // The table of contents follows the title heading
+        let title = output.html.find(r#"<h1 id="title">"#).unwrap();
+        let toc = output.html.find(r#"<nav class="toc">"#).unwrap();
+        assert!(title < toc);
+    }
+
+    #[test]
+    fn test_toc_option_inserts_at_top() {
+        let options = HtmlOptions {
+            toc: true,
+            ..Default::default()
+        };
+        let output = markdown_to_html_with("# One\n\n# Two", &options).unwrap();
+        assert!(output.has_toc);
+        assert!(output.html.starts_with(r#"<nav class="toc">"#));
+
+        let output = markdown_to_html_with("# One", &HtmlOptions::default()).unwrap();
+        assert!(!output.has_toc);
+        assert!(!output.html.contains("toc"));
+    }
+
+    #[test]
+    fn test_highlight_fenced_code() {
+        let options = HtmlOptions {
+            highlight: true,
+            ..Default::default()
+        };
+        let markdown = "```rust\nfn main() {}\n```\n\n```nosuchlang\n<raw>\n```\n\n```\nplain\n```";
+        let html = markdown_to_html_with(markdown, &options).unwrap().html;
+
+        assert!(html.contains(r#"<pre class="hl-code"><code class="language-rust">"#));
+        assert!(html.contains(r#"<span class="hl-"#));
+        // Unknown languages fall back to escaped plain text
+        assert!(html.contains(r#"<code class="language-nosuchlang">&lt;raw&gt;"#));
+        assert!(html.contains("<pre><code>plain"));
+        assert_eq!(html.matches("code-wrapper").count(), 3);
+    }
+
+    #[test]
+    fn test_math_to_mathml() {
+        let html = markdown_to_html("Energy $E = mc^2$.\n\n$$\\frac{a}{b}$$").unwrap();
+        assert!(
+            html.contains(r#"<math xmlns="http://www.w3.org/1998/Math/MathML" display="inline">"#)
+        );
+        assert!(html.contains(r#"<span class="math-display no-break"><math"#));
+        assert!(html.contains("<mfrac>"));
+        assert!(!html.contains('$'));
+    }
+
+    #[test]
+    fn test_math_is_sanitized() {
+        let html =
+            markdown_to_html(r"Formula $\text{<img src=x onerror=alert(1)>}$ and $a<b$").unwrap();
+        assert!(html.contains("<math"));
+        assert!(!html.contains("<img"), "{}", html);
+        assert!(html.contains("<mo>&lt;</mo>"));
+
+        let mathml = sanitize_mathml(
+            r#"<math display="block"><mi onclick="x()">a</mi><mtext><script>x()</script></mtext></math>"#,
+        );
+        assert_eq!(
+            mathml,
+            r#"<math display="block"><mi>a</mi><mtext></mtext></math>"#
+        );
+    }
+
+    #[test]
+    fn test_math_error_reports_line() {
+        let options = HtmlOptions {
+            line_offset: 3,
+            ..Default::default()
+        };
+        let err = markdown_to_html_with("Intro\n\nBad $\\left( x$ here", &options).unwrap_err();
+        match err {
+            Md2PdfError::Math { line, formula, .. } => {
+                assert_eq!(line, 6);
+                assert_eq!(formula, "\\left( x");
+            }
+            other => panic!("unexpected error: {other}"),
+        }
+    }
+
+    #[test]
+    fn test_diagram_code_blocks() {
+        let mut diagrams = Diagrams::new();
+        diagrams.add(diagram::CommandRenderer::new("svgcat", "cat").unwrap());
+        diagrams.add(diagram::CommandRenderer::new("broken", "md2pdf-no-such-command").unwrap());
+        let mut options = HtmlOptions {
+            diagrams: Some(&diagrams),
+            ..Default::default()
+        };
+
+        let markdown = "```svgcat\n<svg onload=\"alert(1)\"><circle/></svg>\n```\n\n```broken\ndigraph {}\n```";
+        let html = markdown_to_html_with(markdown, &options).unwrap().html;
+        // Diagram output is sanitized like raw HTML
+        assert!(html.contains(
+            r#"<figure class="diagram diagram-svgcat"><svg><circle></circle></svg></figure>"#
+        ));
+        // A failed diagram stays a code block
+        assert!(html.contains(r#"<code class="language-broken">digraph {}"#));
+
+        options.strict = true;
+        assert!(matches!(
+            markdown_to_html_with(markdown, &options),
+            Err(Md2PdfError::Diagram { .. })
+        ));
+    }
+
+    #[test]
+    fn test_sanitize_html() {
+        let dirty = r#"<p onclick="x()">Hi<script>alert(1)</script></p><iframe src="https://example.com"></iframe><a href="javascript:alert(1)">link</a><span class="note" style="color: red">ok</span>"#;
+        let clean = sanitize_html(dirty);
+        assert!(!clean.contains("script"));
+        assert!(!clean.contains("iframe"));
+        assert!(!clean.contains("onclick"));
+        assert!(!clean.contains("javascript"));
+        assert!(clean.contains(r#"<span class="note" style="color: red">ok</span>"#));
+    }
+
+    #[test]
+    fn test_raw_html_modes() {
+        let markdown =
+            "Text <b>bold</b> $x$\n\n<script>alert(1)</script>\n\n```rust\nlet x = 1;\n```";
+        let render = |raw_html| {
+            let options = HtmlOptions {
+                raw_html,
+                highlight: true,
+                ..Default::default()
+            };
+            markdown_to_html_with(markdown, &options).unwrap().html
+        };
+
+        // Sanitizing keeps generated markup such as MathML and highlighting
+        let html = render(RawHtml::Sanitize);
+        assert!(html.contains("<b>bold</b>"));
+        assert!(!html.contains("<script>"));
+        assert!(html.contains("<math"));
+        assert!(html.contains(r#"<span class="hl-"#));
+        assert!(html.contains(r#"<div class="code-wrapper no-break">"#));
+
+        let html = render(RawHtml::Allow);
+        assert!(html.contains("<script>alert(1)</script>"));
+
+        let html = render(RawHtml::Escape);
+        assert!(html.contains("&lt;b&gt;bold&lt;/b&gt;"));
+        assert!(html.contains("&lt;script&gt;"));
+        assert!(html.contains("<math"));
+    }
+
+    #[test]
+    fn test_code_block_info_parse() {
+        let info = CodeBlockInfo::parse(r#"rust {linenos=true hl_lines="3-5 8" start=10}"#);
+        assert_eq!(info.language.as_deref(), Some("rust"));
+        assert!(info.line_numbers);
+        assert_eq!(info.highlight_lines, vec![(3, 5), (8, 8)]);
+        assert_eq!(info.start, 10);
+
+        let info = CodeBlockInfo::parse(r#"{linenos, hl_lines="2,4"}"#);
+        assert_eq!(info.language, None);
+        assert!(info.line_numbers);
+        assert_eq!(info.highlight_lines, vec![(2, 2), (4, 4)]);
+        assert_eq!(info.start, 1);
+
+        // Invalid values are ignored
+        let info = CodeBlockInfo::parse(r#"python {hl_lines="5-2" start=x}"#);
+        assert!(info.highlight_lines.is_empty());
+        assert_eq!(info.start, 1);
+        assert_eq!(
+            CodeBlockInfo::parse("text"),
+            CodeBlockInfo {
+                language: Some("text".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_code_listing_with_line_numbers() {
+        let markdown = "```text {linenos=true hl_lines=\"2\" start=10}\na\n<b>\nc\n```";
+        let html = markdown_to_html(markdown).unwrap();
+
+        assert!(html.contains(r#"<div class="code-wrapper code-listing">"#));
+        assert!(!html.contains("no-break"));
+        assert!(html.contains("text (continued)"));
+        assert!(html.contains(r#"<td class="line-number">10</td><td class="line-code"><code class="language-text">a</code></td>"#));
+        assert!(html.contains(r#"<tr class="code-line code-line-highlighted"><td class="line-number">11</td><td class="line-code"><code class="language-text">&lt;b&gt;</code>"#));
+        assert_eq!(html.matches("<tr class=\"code-line").count(), 3);
+    }
+
+    #[test]
+    fn test_long_code_block_can_split() {
+        let code: String = (0..40).map(|i| format!("line {}\n", i)).collect();
+        let html = markdown_to_html(&format!("```\n{}```", code)).unwrap();
+        assert!(html.contains("code-listing"));
+        assert!(!html.contains("line-number"));
+
+        let html = markdown_to_html("```\nshort\n```").unwrap();
+        assert!(html.contains(r#"<div class="code-wrapper no-break"><pre><code>short"#));
+    }
+
+    #[test]
+    fn test_split_html_lines_balances_spans() {
+        let html = r#"<span class="a">one
+<span class="b">two</span>
+three</span>
+"#;
+        assert_eq!(
+            split_html_lines(html),
+            vec![
+                r#"<span class="a">one</span>"#,
+                r#"<span class="a"><span class="b">two</span></span>"#,
+                r#"<span class="a">three</span>"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fill_toc_page_numbers() {
+        let headings = vec![
+            Heading {
+                level: 1,
+                id: "intro".to_string(),
+                text: "Intro".to_string(),
+            },
+            Heading {
+                level: 2,
+                id: "usage".to_string(),
+                text: "Usage".to_string(),
+            },
+        ];
+        let toc = table_of_contents(&headings, 3);
+        let pages = HashMap::from([("intro".to_string(), 1), ("usage".to_string(), 4)]);
+        let filled = fill_toc_page_numbers(&toc, &pages);
+
+        assert!(filled.contains(r#"<span class="toc-page" data-toc-target="intro">1</span>"#));
+        assert!(filled.contains(r#"<span class="toc-page" data-toc-target="usage">4</span>"#));
+    }
+
+    #[test]
+    fn test_chapter_links_are_rewritten() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().canonicalize().unwrap();
+        std::fs::write(dir.join("intro.md"), "# Intro").unwrap();
+        std::fs::write(dir.join("setup.md"), "# Setup").unwrap();
+
+        let chapter = Chapter {
+            id: "intro".to_string(),
+            ids: HashMap::from([
+                (dir.join("intro.md"), "intro".to_string()),
+                (dir.join("setup.md"), "setup".to_string()),
+            ]),
+        };
+        let options = HtmlOptions {
+            base_dir: Some(dir.clone()),
+            chapter: Some(&chapter),
+            ..Default::default()
+        };
+        let markdown = "# Intro {#start}\n\n## Goals\n\n\
+            [goals](#goals) [install](setup.md#install) [setup](./setup.md) \
+            [web](https://example.com/a.md) [notes](notes.txt) ![logo](logo.png)\n\n\
+            Note[^1].\n\n[^1]: Footnote.";
+        let output = markdown_to_html_with(markdown, &options).unwrap();
+
+        let ids: Vec<_> = output.headings.iter().map(|h| h.id.as_str()).collect();
+        assert_eq!(ids, vec!["intro--start", "intro--goals"]);
+        assert!(output.html.contains(r##"<h2 id="intro--goals">"##));
+        assert!(output.html.contains(r##"href="#intro--goals""##));
+        assert!(output.html.contains(r##"href="#setup--install""##));
+        assert!(output.html.contains(r##"href="#setup""##));
+        assert!(output.html.contains(r#"href="https://example.com/a.md""#));
+        let base = directory_url(&dir).unwrap();
+        assert!(output
+            .html
+            .contains(&format!(r#"href="{}""#, base.join("notes.txt").unwrap())));
+        assert!(output
+            .html
+            .contains(&format!(r#"src="{}""#, base.join("logo.png").unwrap())));
+        assert!(output.html.contains(r##"href="#intro--1""##));
+        assert!(output.html.contains(r#"id="intro--1""#));
+    }
+
+    fn embed_options(dir: &Path, strict: bool) -> HtmlOptions<'static> {
+        HtmlOptions {
+            base_dir: Some(dir.to_path_buf()),
+            embed_images: true,
+            strict,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_embed_local_images() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(temp_dir.path().join("img")).unwrap();
+        std::fs::write(
+            temp_dir.path().join("img/arch.png"),
+            b"\x89PNG\r\n\x1a\nrest-of-image",
+        )
+        .unwrap();
+        std::fs::write(
+            temp_dir.path().join("img/logo.svg"),
+            r#"<?xml version="1.0"?><svg xmlns="http://www.w3.org/2000/svg"/>"#,
+        )
+        .unwrap();
+
+        let markdown =
+            "![arch](./img/arch.png) ![logo](img/logo.svg) ![remote](https://example.com/a.png)";
+        let html = markdown_to_html_with(markdown, &embed_options(temp_dir.path(), true))
+            .unwrap()
+            .html;
+
+        assert!(html.contains(r#"src="data:image/png;base64,iVBORw0KGg"#));
+        assert!(html.contains(r#"src="data:image/svg+xml;base64,"#));
+        assert!(html.contains(r#"src="https://example.com/a.png""#));
+    }
+
+    #[test]
+    fn test_embed_missing_image() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let markdown = "![missing](missing.png)";
+
+        let html = markdown_to_html_with(markdown, &embed_options(temp_dir.path(), false))
+            .unwrap()
+            .html;
+        assert!(html.contains(r#"src="missing.png""#));
+
+        let result = markdown_to_html_with(markdown, &embed_options(temp_dir.path(), true));
+        assert!(matches!(result, Err(Md2PdfError::MissingImage(_))));
+    }
+
+    #[test]
+    fn test_local_images() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().canonicalize().unwrap();
+        let markdown = "![a](img/a.png) ![b](https://example.com/b.png)\n\n![again](./img/a.png) ![c](../c.svg)";
+
+        let images = local_images(markdown, &dir).unwrap();
+        assert_eq!(
+            images,
+            vec![dir.join("img/a.png"), dir.parent().unwrap().join("c.svg")]
+        );
+    }
+
+    #[test]
+    fn test_sniff_image_mime() {
+        let path = Path::new("image.bin");
+        assert_eq!(
+            sniff_image_mime(&[0xFF, 0xD8, 0xFF, 0xE0], path),
+            Some("image/jpeg")
+        );
+        assert_eq!(sniff_image_mime(b"GIF89a....", path), Some("image/gif"));
+        assert_eq!(
+            sniff_image_mime(b"RIFF\x00\x00\x00\x00WEBPVP8 ", path),
+            Some("image/webp")
+        );
+        assert_eq!(sniff_image_mime(b"plain text", path), None);
+        assert_eq!(
+            sniff_image_mime(b"plain text", Path::new("photo.JPG")),
+            Some("image/jpeg")
+        );
+    }
 }
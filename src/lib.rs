@@ -15,11 +15,17 @@
 //!
 //! The library is organized into several modules:
 //!
+//! - `config`: Configuration files with layered defaults
 //! - `error`: Custom error types using thiserror
 //! - `markdown`: Markdown file reading and validation
 //! - `html`: HTML generation with semantic markup
+//! - `diagram`: Diagram code blocks rendered to inline SVG
+//! - `highlight`: Syntax highlighting for fenced code blocks
 //! - `pdf`: PDF generation using headless Chrome
+//! - `project`: Book manifests describing documents merged from several files
+//! - `serve`: Live HTML preview server
 //! - `template`: HTML templating and CSS styling
+//! - `watch`: Watching source files to rebuild on change
 //!
 //! ## Example
 //!
@@ -34,34 +40,102 @@
 //!     &options
 //! ).expect("Conversion failed");
 //! ```
+//!
+//! Markdown held in memory can be rendered straight to PDF bytes with
+//! [`render`], or to any [`std::io::Write`] with [`render_to_writer`].
 
+pub mod config;
+pub mod diagram;
 pub mod error;
+pub mod highlight;
 pub mod html;
 pub mod markdown;
 pub mod pdf;
+pub mod project;
+pub mod serve;
 pub mod template;
+pub mod watch;
 
 use error::Result;
 use log::{debug, info};
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
 
 /// Options for markdown to PDF conversion
 #[derive(Debug, Clone)]
 pub struct ConversionOptions {
     /// Optional custom CSS file path
     pub custom_css_path: Option<String>,
+    /// Custom CSS file used when neither `custom_css_path` nor a book
+    /// manifest names one, such as the stylesheet of a configuration file
+    pub custom_css_default: Option<String>,
     /// PDF generation configuration
     pub pdf_config: pdf::PdfConfig,
     /// Enable verbose logging
     pub verbose: bool,
+    /// Inline local images as data URIs
+    pub embed_images: bool,
+    /// Treat recoverable problems (such as missing images) as errors
+    pub strict: bool,
+    /// Variables available as `{{name}}` in header and footer templates
+    pub variables: BTreeMap<String, String>,
+    /// Document metadata overriding values from front matter
+    pub metadata: markdown::DocumentMetadata,
+    /// Document metadata used where front matter leaves a field unset, such
+    /// as values from configuration files; extra keys become variables
+    pub metadata_defaults: markdown::DocumentMetadata,
+    /// Insert a table of contents even without a `[TOC]` marker
+    pub toc: bool,
+    /// Deepest heading level listed in the table of contents
+    pub toc_depth: u8,
+    /// Theme for code block syntax highlighting, `None` to turn it off
+    pub highlight_theme: Option<String>,
+    /// Commands rendering diagram code blocks to SVG, keyed by language;
+    /// none are run unless configured
+    pub diagram_commands: BTreeMap<String, String>,
+    /// Mermaid library bundle used to render `mermaid` code blocks instead
+    /// of the bundled build
+    pub mermaid_script: Option<PathBuf>,
+    /// How raw HTML in the markdown is handled
+    pub raw_html: html::RawHtml,
+    /// Directory relative images and links resolve against; file
+    /// conversions default to the input file's directory
+    pub base_dir: Option<PathBuf>,
+}
+
+impl ConversionOptions {
+    /// The custom stylesheet in effect, if any
+    pub fn css_path(&self) -> Option<&str> {
+        self.custom_css_path
+            .as_deref()
+            .or(self.custom_css_default.as_deref())
+    }
 }
 
 impl Default for ConversionOptions {
     fn default() -> Self {
         Self {
             custom_css_path: None,
+            custom_css_default: None,
             pdf_config: pdf::PdfConfig::default(),
             verbose: false,
+            embed_images: false,
+            strict: false,
+            variables: BTreeMap::new(),
+            metadata: markdown::DocumentMetadata::default(),
+            metadata_defaults: markdown::DocumentMetadata::default(),
+            toc: false,
+            toc_depth: 3,
+            highlight_theme: Some(highlight::DEFAULT_THEME.to_string()),
+            diagram_commands: BTreeMap::new(),
+            mermaid_script: None,
+            raw_html: html::RawHtml::Sanitize,
+            base_dir: None,
         }
     }
 }
@@ -74,8 +148,8 @@ impl Default for ConversionOptions {
 ///
 /// # Arguments
 ///
-/// * `input_path` - Path to the input markdown file (.md)
-/// * `output_path` - Path where the PDF should be written (.pdf)
+/// * `input_path` - Path to the input markdown file (.md), or `-` for stdin
+/// * `output_path` - Path where the PDF should be written (.pdf), or `-` for stdout
 /// * `options` - Conversion options including custom CSS and PDF config
 ///
 /// # Errors
@@ -104,6 +178,21 @@ pub fn convert_markdown_to_pdf(
     input_path: &Path,
     output_path: &Path,
     options: &ConversionOptions,
+) -> Result<()> {
+    let renderer = pdf::Renderer::new();
+    convert_markdown_to_pdf_with(&renderer, input_path, output_path, options)
+}
+
+/// Convert a single Markdown file to PDF using an existing renderer
+///
+/// Behaves like [`convert_markdown_to_pdf`] but reuses the given
+/// [`pdf::Renderer`], so the headless browser is only launched once across
+/// several conversions.
+pub fn convert_markdown_to_pdf_with(
+    renderer: &pdf::Renderer,
+    input_path: &Path,
+    output_path: &Path,
+    options: &ConversionOptions,
 ) -> Result<()> {
     info!(
         "Starting conversion: {} -> {}",
@@ -111,50 +200,603 @@ pub fn convert_markdown_to_pdf(
         output_path.display()
     );
 
-    let html_title = match output_path.file_stem() {
-        Some(stem) => stem.to_string_lossy().to_string(),
-        None => "Document".to_string(),
+    // Step 1: Read markdown file
+    debug!("Reading markdown file: {}", input_path.display());
+    let markdown_content = markdown::read_markdown_file(input_path)?;
+
+    // Prepare the output path before the expensive rendering work
+    debug!("Preparing output path: {}", output_path.display());
+    pdf::prepare_output_path(output_path)?;
+
+    // Relative images and links resolve against the markdown file's
+    // directory unless a base directory was given
+    let mut options = options.clone();
+    if options.base_dir.is_none() {
+        options.base_dir = input_path.parent().map(Path::to_path_buf);
+    }
+
+    // Without a title in the metadata, the output or input file name is used
+    let default_title = [output_path, input_path]
+        .into_iter()
+        .filter(|path| !markdown::is_stdio(path))
+        .find_map(|path| path.file_stem())
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| DEFAULT_TITLE.to_string());
+
+    let source = Source {
+        markdown: &markdown_content,
+        base_dir: options.base_dir.clone(),
+        section: None,
     };
-    // .and_then(|s| s.to_str())
-    // .unwrap_or("Document");
+    let pdf_data = render_document(renderer, &[source], &options, &default_title)?;
+    pdf::write_pdf(output_path, &pdf_data)?;
+
+    info!("Conversion completed successfully");
+    Ok(())
+}
 
-    // Step 1: Read and validate markdown file
+/// Convert a single Markdown file to the HTML page that is printed to PDF
+///
+/// The page is what [`template::generate_html`] produces for the file, as
+/// Chrome receives it, before page numbers are filled into a table of
+/// contents. Relative references are kept; they resolve against the input
+/// file's directory unless [`ConversionOptions::base_dir`] is set. The
+/// renderer is only used by diagrams that need a browser.
+pub fn convert_markdown_to_html_with(
+    renderer: &pdf::Renderer,
+    input_path: &Path,
+    options: &ConversionOptions,
+) -> Result<String> {
     debug!("Reading markdown file: {}", input_path.display());
     let markdown_content = markdown::read_markdown_file(input_path)?;
-    markdown::validate_markdown(&markdown_content)?;
+
+    let base_dir = options
+        .base_dir
+        .clone()
+        .or_else(|| input_path.parent().map(Path::to_path_buf));
+    let default_title = input_path
+        .file_stem()
+        .filter(|_| !markdown::is_stdio(input_path))
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| DEFAULT_TITLE.to_string());
+
+    let source = Source {
+        markdown: &markdown_content,
+        base_dir,
+        section: None,
+    };
+    build_html(renderer, &[source], options, &default_title).map(|document| document.page())
+}
+
+/// Merge several Markdown files into a single PDF
+///
+/// The files become consecutive chapters in the order given, each starting
+/// on a new page. Links between them, such as `[setup](chapter2.md#setup)`,
+/// become links within the PDF. The front matter of the first file that has
+/// one supplies the document metadata; front matter in later files is
+/// ignored.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use md2pdf::{convert_many_to_one, ConversionOptions};
+/// use std::path::Path;
+///
+/// let chapters = ["intro.md", "usage.md", "faq.md"];
+/// convert_many_to_one(&chapters, Path::new("handbook.pdf"), &ConversionOptions::default())
+///     .expect("Failed to merge");
+/// ```
+pub fn convert_many_to_one(
+    inputs: &[impl AsRef<Path>],
+    output_path: &Path,
+    options: &ConversionOptions,
+) -> Result<()> {
+    let renderer = pdf::Renderer::new();
+    convert_many_to_one_with(&renderer, inputs, output_path, options)
+}
+
+/// Merge several Markdown files into a single PDF using an existing renderer
+///
+/// Behaves like [`convert_many_to_one`] but reuses the given
+/// [`pdf::Renderer`].
+pub fn convert_many_to_one_with(
+    renderer: &pdf::Renderer,
+    inputs: &[impl AsRef<Path>],
+    output_path: &Path,
+    options: &ConversionOptions,
+) -> Result<()> {
+    let entries: Vec<_> = inputs
+        .iter()
+        .map(|input| project::BookEntry::Chapter {
+            path: input.as_ref().to_path_buf(),
+            kind: project::ChapterKind::Chapter,
+            css: None,
+        })
+        .collect();
+    merge_entries(renderer, &entries, output_path, options)
+}
+
+/// Convert a book described by a manifest into a single PDF
+///
+/// The book's title, author and stylesheet apply unless `options` already
+/// sets them. Each part opens with a page showing its title, the title page
+/// is left out of the table of contents and bookmarks, and per-chapter
+/// stylesheets only apply within their chapter.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use md2pdf::{convert_book, project::Book, ConversionOptions};
+/// use std::path::Path;
+///
+/// let book = Book::load(Path::new("docs/SUMMARY.md")).expect("Invalid manifest");
+/// convert_book(&book, &book.output_path(), &ConversionOptions::default())
+///     .expect("Failed to convert");
+/// ```
+pub fn convert_book(
+    book: &project::Book,
+    output_path: &Path,
+    options: &ConversionOptions,
+) -> Result<()> {
+    let renderer = pdf::Renderer::new();
+    convert_book_with(&renderer, book, output_path, options)
+}
+
+/// Convert a book into a single PDF using an existing renderer
+///
+/// Behaves like [`convert_book`] but reuses the given [`pdf::Renderer`].
+pub fn convert_book_with(
+    renderer: &pdf::Renderer,
+    book: &project::Book,
+    output_path: &Path,
+    options: &ConversionOptions,
+) -> Result<()> {
+    info!("Converting book: {}", book.manifest.display());
+    book.validate()?;
+    let options = book.conversion_options(options);
+    merge_entries(renderer, &book.entries(), output_path, &options)
+}
+
+/// Merge book entries into one document and write it as PDF
+fn merge_entries(
+    renderer: &pdf::Renderer,
+    entries: &[project::BookEntry],
+    output_path: &Path,
+    options: &ConversionOptions,
+) -> Result<()> {
+    if entries.is_empty() {
+        return Err(error::Md2PdfError::NoInputFiles);
+    }
+    info!(
+        "Merging {} chapter(s) -> {}",
+        entries.len(),
+        output_path.display()
+    );
+
+    // Read every chapter and give it an id before converting any, so links
+    // can point forward to later chapters
+    struct Entry {
+        markdown: String,
+        base_dir: Option<PathBuf>,
+        id: String,
+        kind: Option<project::ChapterKind>,
+        css: Option<String>,
+    }
+    let mut loaded = Vec::with_capacity(entries.len());
+    let mut ids = HashMap::new();
+    let mut used_ids = HashSet::new();
+    for entry in entries {
+        match entry {
+            project::BookEntry::Part(title) => {
+                let id = html::unique_slug(title, &mut used_ids);
+                used_ids.insert(id.clone());
+                loaded.push(Entry {
+                    markdown: format!("# {} {{.part-title}}\n", markdown::escape_markdown(title)),
+                    base_dir: None,
+                    id,
+                    kind: None,
+                    css: None,
+                });
+            }
+            project::BookEntry::Chapter { path, kind, css } => {
+                debug!("Reading chapter: {}", path.display());
+                let markdown_content = markdown::read_markdown_file(path)?;
+                if markdown::validate_markdown(&markdown_content).is_err() {
+                    return Err(error::Md2PdfError::MarkdownParse(format!(
+                        "Chapter file is empty: {}",
+                        path.display()
+                    )));
+                }
+                let canonical = path
+                    .canonicalize()
+                    .map_err(|_| error::Md2PdfError::InvalidPath(path.to_path_buf()))?;
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let id = html::unique_slug(&stem, &mut used_ids);
+                used_ids.insert(id.clone());
+                ids.insert(canonical.clone(), id.clone());
+                loaded.push(Entry {
+                    markdown: markdown_content,
+                    base_dir: canonical.parent().map(Path::to_path_buf),
+                    id,
+                    kind: Some(*kind),
+                    css: css
+                        .as_deref()
+                        .map(|css| template::load_css(Some(css)))
+                        .transpose()?,
+                });
+            }
+        }
+    }
+
+    debug!("Preparing output path: {}", output_path.display());
+    pdf::prepare_output_path(output_path)?;
+
+    // The document itself resolves against the directory holding all chapters
+    let mut options = options.clone();
+    if options.base_dir.is_none() {
+        options.base_dir =
+            common_ancestor(loaded.iter().filter_map(|entry| entry.base_dir.as_deref()));
+    }
+
+    let sources: Vec<_> = loaded
+        .iter()
+        .map(|entry| Source {
+            markdown: &entry.markdown,
+            base_dir: entry.base_dir.clone(),
+            section: Some(Section {
+                links: html::Chapter {
+                    id: entry.id.clone(),
+                    ids: ids.clone(),
+                },
+                class: entry
+                    .kind
+                    .map_or("chapter part", project::ChapterKind::class),
+                css: entry.css.clone(),
+                listed: entry.kind != Some(project::ChapterKind::TitlePage),
+            }),
+        })
+        .collect();
+
+    let default_title = output_path
+        .file_stem()
+        .filter(|_| !markdown::is_stdio(output_path))
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| DEFAULT_TITLE.to_string());
+
+    let pdf_data = render_document(renderer, &sources, &options, &default_title)?;
+    pdf::write_pdf(output_path, &pdf_data)?;
+
+    info!("Merge completed successfully");
+    Ok(())
+}
+
+/// Deepest directory containing all of `dirs`
+fn common_ancestor<'a>(mut dirs: impl Iterator<Item = &'a Path>) -> Option<PathBuf> {
+    let mut ancestor = dirs.next()?.to_path_buf();
+    for dir in dirs {
+        while !dir.starts_with(&ancestor) {
+            if !ancestor.pop() {
+                return None;
+            }
+        }
+    }
+    Some(ancestor)
+}
+
+/// Title used when neither the metadata nor a file name supplies one
+const DEFAULT_TITLE: &str = "Document";
+
+/// Render markdown held in memory to PDF bytes
+///
+/// No output file is written: the PDF is returned. The generated HTML is
+/// staged in a temporary file for Chrome to load and removed once the page
+/// is printed. Assets the document references are read from disk, resolving
+/// against [`ConversionOptions::base_dir`].
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use md2pdf::{render, ConversionOptions};
+///
+/// let options = ConversionOptions {
+///     base_dir: Some("docs".into()),
+///     ..Default::default()
+/// };
+/// let pdf = render("# Report\n\n![chart](chart.png)", &options)
+///     .expect("Failed to render");
+/// assert!(pdf.starts_with(b"%PDF"));
+/// ```
+pub fn render(markdown: &str, options: &ConversionOptions) -> Result<Vec<u8>> {
+    let renderer = pdf::Renderer::new();
+    render_with(&renderer, markdown, options)
+}
+
+/// Render markdown to PDF bytes using an existing renderer
+///
+/// Behaves like [`render`] but reuses the given [`pdf::Renderer`].
+pub fn render_with(
+    renderer: &pdf::Renderer,
+    markdown: &str,
+    options: &ConversionOptions,
+) -> Result<Vec<u8>> {
+    let source = Source {
+        markdown,
+        base_dir: options.base_dir.clone(),
+        section: None,
+    };
+    render_document(renderer, &[source], options, DEFAULT_TITLE)
+}
+
+/// Render markdown to PDF and write it to `writer`
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use md2pdf::{render_to_writer, ConversionOptions};
+///
+/// let mut buffer = Vec::new();
+/// render_to_writer("# Hello", &mut buffer, &ConversionOptions::default())
+///     .expect("Failed to render");
+/// ```
+pub fn render_to_writer<W: Write>(
+    markdown: &str,
+    writer: &mut W,
+    options: &ConversionOptions,
+) -> Result<()> {
+    let renderer = pdf::Renderer::new();
+    render_to_writer_with(&renderer, markdown, writer, options)
+}
+
+/// Render markdown to PDF and write it to `writer` using an existing renderer
+///
+/// Behaves like [`render_to_writer`] but reuses the given [`pdf::Renderer`].
+pub fn render_to_writer_with<W: Write>(
+    renderer: &pdf::Renderer,
+    markdown: &str,
+    writer: &mut W,
+    options: &ConversionOptions,
+) -> Result<()> {
+    let pdf_data = render_with(renderer, markdown, options)?;
+    writer
+        .write_all(&pdf_data)
+        .and_then(|_| writer.flush())
+        .map_err(error::Md2PdfError::Output)
+}
+
+/// One markdown text going into a document
+struct Source<'a> {
+    markdown: &'a str,
+    /// Directory its relative images and links resolve against
+    base_dir: Option<PathBuf>,
+    /// Its section in a document merged from several files
+    section: Option<Section>,
+}
+
+/// A chapter's `<section>` in a merged document
+struct Section {
+    /// The chapter's id and the ids of the chapters it may link to
+    links: html::Chapter,
+    /// Classes of the section element
+    class: &'static str,
+    /// Stylesheet scoped to the section
+    css: Option<String>,
+    /// Whether its headings appear in the table of contents and bookmarks
+    listed: bool,
+}
+
+/// Metadata of a document: the first front matter over the defaults, under
+/// metadata given explicitly
+fn document_metadata(
+    sources: &[Source],
+    options: &ConversionOptions,
+) -> Result<markdown::DocumentMetadata> {
+    let mut metadata = options.metadata_defaults.clone();
+    for source in sources {
+        let (front_matter, body) = markdown::split_front_matter(source.markdown)?;
+        if body.len() < source.markdown.len() {
+            metadata.merge(&front_matter);
+            break;
+        }
+    }
+    metadata.merge(&options.metadata);
+    Ok(metadata)
+}
+
+/// A document converted to HTML, not yet printed
+struct HtmlDocument {
+    /// The body, without the page around it
+    content: String,
+    /// Default and custom styles, highlight theme and chapter styles
+    css: String,
+    /// Title of the HTML page
+    title: String,
+    metadata: markdown::DocumentMetadata,
+    /// Variables for header and footer templates
+    variables: BTreeMap<String, String>,
+    headings: Vec<html::Heading>,
+    /// Whether the document has a table of contents awaiting page numbers
+    has_toc: bool,
+}
+
+impl HtmlDocument {
+    /// The complete HTML page
+    fn page(&self) -> String {
+        template::generate_html(&self.content, &self.css, &self.title)
+    }
+}
+
+/// The first half of the conversion pipeline, from markdown sources to HTML
+fn build_html(
+    renderer: &pdf::Renderer,
+    sources: &[Source],
+    options: &ConversionOptions,
+    default_title: &str,
+) -> Result<HtmlDocument> {
+    if sources.is_empty() {
+        return Err(error::Md2PdfError::NoInputFiles);
+    }
+    for source in sources {
+        markdown::validate_markdown(source.markdown)?;
+    }
+
+    // Front matter supplies the title, PDF metadata and template variables
+    let mut metadata = document_metadata(sources, options)?;
+    let html_title = metadata
+        .title
+        .clone()
+        .unwrap_or_else(|| default_title.to_string());
+    metadata.title.get_or_insert_with(|| html_title.clone());
+
+    // Variables given explicitly take precedence over front matter
+    let mut variables = metadata.variables();
+    variables.extend(options.variables.clone());
 
     // Step 2: Convert markdown to HTML
     debug!("Converting markdown to HTML");
-    let html_content = html::markdown_to_html(&markdown_content)?;
+    let diagrams = diagram::Diagrams::from_config(
+        &options.diagram_commands,
+        options.mermaid_script.as_deref(),
+        renderer,
+    )?;
+    let merged = sources.iter().any(|source| source.section.is_some());
+    let mut html_content = String::new();
+    let mut headings = Vec::new();
+    let mut has_toc = false;
+    // A book's table of contents goes after its title page
+    let mut toc_position = 0;
+    for source in sources {
+        let (_, body) = markdown::split_front_matter(source.markdown)?;
+        let front_matter_lines = source.markdown[..source.markdown.len() - body.len()]
+            .matches('\n')
+            .count();
+        let html_options = html::HtmlOptions {
+            base_dir: source.base_dir.clone(),
+            embed_images: options.embed_images,
+            strict: options.strict,
+            // A merged document gets one table of contents for all chapters
+            toc: options.toc && !merged,
+            toc_depth: options.toc_depth,
+            highlight: options.highlight_theme.is_some(),
+            line_offset: front_matter_lines,
+            diagrams: Some(&diagrams),
+            raw_html: options.raw_html,
+            chapter: source.section.as_ref().map(|section| &section.links),
+        };
+        let html_output = html::markdown_to_html_with(body, &html_options)?;
+        let start = html_content.len();
+        match &source.section {
+            Some(section) => html_content.push_str(&format!(
+                "<section class=\"{}\" id=\"{}\">\n{}</section>\n",
+                section.class,
+                template::escape_html(&section.links.id),
+                html_output.html
+            )),
+            None => html_content.push_str(&html_output.html),
+        }
+        if source.section.as_ref().is_none_or(|section| section.listed) {
+            headings.extend(html_output.headings);
+        } else if toc_position == start {
+            toc_position = html_content.len();
+        }
+        has_toc |= html_output.has_toc;
+    }
+    if merged && options.toc {
+        html_content.insert_str(
+            toc_position,
+            &html::table_of_contents(&headings, options.toc_depth),
+        );
+        has_toc = true;
+    }
+    if options.pdf_config.outline_depth > 0 {
+        html_content.push_str(&html::heading_anchor_links(&headings));
+    }
 
     // Step 3: Load CSS (custom or default)
     debug!("Loading CSS");
-    let css = match &options.custom_css_path {
-        Some(css_path) => template::load_css(Some(Path::new(css_path)))?,
-        None => template::load_css(None)?,
-    };
+    let mut css = template::load_css(options.css_path().map(Path::new))?;
+    if let Some(theme) = &options.highlight_theme {
+        css.push('\n');
+        css.push_str(&highlight::theme_css(theme)?);
+    }
+    for source in sources {
+        if let Some(Section {
+            links,
+            css: Some(chapter_css),
+            ..
+        }) = &source.section
+        {
+            css.push_str(&format!(
+                "\n@scope ([id=\"{}\"]) {{\n{}\n}}\n",
+                links.id, chapter_css
+            ));
+        }
+    }
+
+    Ok(HtmlDocument {
+        content: html_content,
+        css,
+        title: html_title,
+        metadata,
+        variables,
+        headings,
+        has_toc,
+    })
+}
+
+/// The conversion pipeline from markdown sources to finished PDF bytes
+fn render_document(
+    renderer: &pdf::Renderer,
+    sources: &[Source],
+    options: &ConversionOptions,
+    default_title: &str,
+) -> Result<Vec<u8>> {
+    let document = build_html(renderer, sources, options, default_title)?;
+    let base_dir = options.base_dir.as_deref();
 
     // Step 4: Generate complete HTML document
     debug!("Generating complete HTML document");
-    let full_html = template::generate_html(&html_content, &css, &html_title);
-
-    // Step 5: Prepare output path
-    debug!("Preparing output path: {}", output_path.display());
-    pdf::prepare_output_path(output_path)?;
+    let full_html = document.page();
 
-    // Step 6: Generate PDF
+    // Step 5: Generate PDF
     debug!("Generating PDF");
-    pdf::generate_pdf(&full_html, output_path, &options.pdf_config)?;
+    let pdf_config = pdf::PdfConfig {
+        header_template: options.pdf_config.header_template.as_deref().map(|header| {
+            template::expand_page_template(header, &document.variables, &options.pdf_config)
+        }),
+        footer_template: options.pdf_config.footer_template.as_deref().map(|footer| {
+            template::expand_page_template(footer, &document.variables, &options.pdf_config)
+        }),
+        ..options.pdf_config.clone()
+    };
+    let mut pdf_data = renderer.render_pdf(&full_html, base_dir, &pdf_config)?;
 
-    info!("Conversion completed successfully");
-    Ok(())
+    // The first render tells us where each heading landed; render again with
+    // those page numbers in the table of contents
+    if document.has_toc {
+        debug!("Filling table of contents page numbers");
+        let pages = pdf::destination_pages(&pdf_data)?;
+        let html_content = html::fill_toc_page_numbers(&document.content, &pages);
+        let full_html = template::generate_html(&html_content, &document.css, &document.title);
+        pdf_data = renderer.render_pdf(&full_html, base_dir, &pdf_config)?;
+    }
+
+    // Step 6: Write document metadata
+    debug!("Writing PDF metadata");
+    let pdf_data = pdf::apply_metadata(&pdf_data, &document.metadata)?;
+
+    // Step 7: Add bookmarks for the heading tree
+    pdf::add_outline(
+        &pdf_data,
+        &document.headings,
+        options.pdf_config.outline_depth,
+    )
 }
 
 /// Convert multiple Markdown files to PDFs
 ///
 /// Batch conversion that processes multiple markdown files. Each file is
 /// converted independently, and errors for individual files are collected
-/// and returned.
+/// and returned. A single headless browser is shared by all conversions.
 ///
 /// # Arguments
 ///
@@ -191,16 +833,120 @@ pub fn convert_multiple_files(
     conversions: &[(impl AsRef<Path>, impl AsRef<Path>)],
     options: &ConversionOptions,
 ) -> Vec<Result<()>> {
+    let renderer = pdf::Renderer::new();
     conversions
         .iter()
-        .map(|(input, output)| convert_markdown_to_pdf(input.as_ref(), output.as_ref(), options))
+        .map(|(input, output)| {
+            convert_markdown_to_pdf_with(&renderer, input.as_ref(), output.as_ref(), options)
+        })
         .collect()
 }
 
+/// Convert multiple Markdown files to PDFs concurrently
+///
+/// Runs up to `jobs` conversions at once, each on its own tab of a single
+/// shared headless browser. Results are returned in the same order as
+/// `conversions`, each paired with the time that conversion took.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use md2pdf::{convert_multiple_files_parallel, ConversionOptions};
+/// use std::path::PathBuf;
+///
+/// let conversions = vec![
+///     (PathBuf::from("doc1.md"), PathBuf::from("doc1.pdf")),
+///     (PathBuf::from("doc2.md"), PathBuf::from("doc2.pdf")),
+/// ];
+///
+/// let options = ConversionOptions::default();
+/// for (result, elapsed) in convert_multiple_files_parallel(&conversions, &options, 4) {
+///     println!("{:?} in {:.2?}", result.is_ok(), elapsed);
+/// }
+/// ```
+pub fn convert_multiple_files_parallel(
+    conversions: &[(impl AsRef<Path> + Sync, impl AsRef<Path> + Sync)],
+    options: &ConversionOptions,
+    jobs: usize,
+) -> Vec<(Result<()>, Duration)> {
+    let renderer = pdf::Renderer::new();
+    let jobs = jobs.clamp(1, conversions.len().max(1));
+    let next = AtomicUsize::new(0);
+
+    let mut results: Vec<Option<(Result<()>, Duration)>> =
+        conversions.iter().map(|_| None).collect();
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut finished = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((input, output)) = conversions.get(index) else {
+                            break;
+                        };
+                        let started = Instant::now();
+                        let result = catch_panic(input.as_ref(), || {
+                            convert_markdown_to_pdf_with(
+                                &renderer,
+                                input.as_ref(),
+                                output.as_ref(),
+                                options,
+                            )
+                        });
+                        finished.push((index, result, started.elapsed()));
+                    }
+                    finished
+                })
+            })
+            .collect();
+
+        // A worker that died anyway leaves its files without a result;
+        // they are reported below while the other files keep theirs
+        for worker in workers {
+            for (index, result, elapsed) in worker.join().unwrap_or_default() {
+                results[index] = Some((result, elapsed));
+            }
+        }
+    });
+
+    results
+        .into_iter()
+        .zip(conversions)
+        .map(|(result, (input, _))| {
+            result.unwrap_or_else(|| {
+                let error = error::Md2PdfError::ConversionPanicked {
+                    path: input.as_ref().to_path_buf(),
+                    message: "conversion worker stopped".to_string(),
+                };
+                (Err(error), Duration::ZERO)
+            })
+        })
+        .collect()
+}
+
+/// Run one conversion of a batch, turning a panic into an error for
+/// `input` so the rest of the batch still runs and is reported
+fn catch_panic(input: &Path, convert: impl FnOnce() -> Result<()>) -> Result<()> {
+    panic::catch_unwind(panic::AssertUnwindSafe(convert)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err(error::Md2PdfError::ConversionPanicked {
+            path: input.to_path_buf(),
+            message,
+        })
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::io::Write;
+    use std::path::PathBuf;
     use tempfile::NamedTempFile;
 
     #[test]
@@ -208,6 +954,51 @@ mod tests {
         let options = ConversionOptions::default();
         assert!(options.custom_css_path.is_none());
         assert!(!options.verbose);
+        assert!(!options.embed_images);
+        assert!(!options.strict);
+        assert!(!options.toc);
+        assert_eq!(options.toc_depth, 3);
+        assert_eq!(
+            options.highlight_theme.as_deref(),
+            Some(highlight::DEFAULT_THEME)
+        );
+        assert!(options.diagram_commands.is_empty());
+        assert!(options.mermaid_script.is_none());
+        assert_eq!(options.raw_html, html::RawHtml::Sanitize);
+        assert_eq!(
+            options.metadata_defaults,
+            markdown::DocumentMetadata::default()
+        );
+    }
+
+    #[test]
+    fn test_document_metadata_precedence() {
+        let mut options = ConversionOptions::default();
+        options.metadata_defaults.title = Some("Config title".to_string());
+        options.metadata_defaults.author = Some("Config author".to_string());
+        options.metadata_defaults.subject = Some("Config subject".to_string());
+        options.metadata.author = Some("CLI author".to_string());
+
+        let source = |markdown| Source {
+            markdown,
+            base_dir: None,
+            section: None,
+        };
+        // The first file with front matter supplies it, even if it isn't
+        // the first file; later front matter is ignored
+        let sources = [
+            source("# No front matter"),
+            source("---\ntitle: Front matter title\nauthor: Front matter author\n---\n# Two"),
+            source("---\nsubject: Third subject\n---\n# Three"),
+        ];
+
+        let metadata = document_metadata(&sources, &options).unwrap();
+        assert_eq!(metadata.title.as_deref(), Some("Front matter title"));
+        assert_eq!(metadata.author.as_deref(), Some("CLI author"));
+        assert_eq!(metadata.subject.as_deref(), Some("Config subject"));
+
+        let metadata = document_metadata(&sources[2..], &options).unwrap();
+        assert_eq!(metadata.subject.as_deref(), Some("Third subject"));
     }
 
     #[test]
@@ -234,4 +1025,150 @@ mod tests {
         std::fs::remove_file(temp_path).unwrap();
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_render_rejects_empty_markdown() {
+        let mut buffer = Vec::new();
+        let result = render_to_writer("  \n", &mut buffer, &ConversionOptions::default());
+        assert!(matches!(result, Err(error::Md2PdfError::MarkdownParse(_))));
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "chrome-tests"),
+        ignore = "requires a local Chrome/Chromium installation"
+    )]
+    fn test_render_to_writer() {
+        let mut buffer = Vec::new();
+        render_to_writer(
+            "# In memory\n\nNo files involved.",
+            &mut buffer,
+            &ConversionOptions::default(),
+        )
+        .unwrap();
+        assert!(buffer.starts_with(b"%PDF"));
+
+        // A shared renderer serves several documents
+        let renderer = pdf::Renderer::new();
+        for markdown in ["# First", "# Second"] {
+            let mut buffer = Vec::new();
+            render_to_writer_with(&renderer, markdown, &mut buffer, &Default::default()).unwrap();
+            assert!(buffer.starts_with(b"%PDF"));
+        }
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "chrome-tests"),
+        ignore = "requires a local Chrome/Chromium installation"
+    )]
+    fn test_render_names_every_heading() {
+        let mut buffer = Vec::new();
+        render_to_writer(
+            "# Intro\n\nText.\n\n## Setup\n\nMore text.",
+            &mut buffer,
+            &ConversionOptions::default(),
+        )
+        .unwrap();
+        let pages = pdf::destination_pages(&buffer).unwrap();
+        assert_eq!(pages.get("intro"), Some(&1));
+        assert_eq!(pages.get("setup"), Some(&1));
+    }
+
+    #[test]
+    fn test_convert_markdown_to_html() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("guide.md");
+        std::fs::write(
+            &input,
+            "---\ntitle: User Guide\n---\n# Setup\n\n![logo](logo.png)\n",
+        )
+        .unwrap();
+
+        let html = convert_markdown_to_html_with(
+            &pdf::Renderer::new(),
+            &input,
+            &ConversionOptions::default(),
+        )
+        .unwrap();
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<title>User Guide</title>"));
+        assert!(html.contains(r#"<h1 id="setup">Setup</h1>"#));
+        assert!(html.contains(r#"src="logo.png""#));
+    }
+
+    #[test]
+    fn test_common_ancestor() {
+        let dirs = [
+            Path::new("/book/part1"),
+            Path::new("/book/part2/extra"),
+            Path::new("/book"),
+        ];
+        assert_eq!(
+            common_ancestor(dirs.into_iter()),
+            Some(PathBuf::from("/book"))
+        );
+        assert_eq!(common_ancestor(std::iter::empty()), None);
+    }
+
+    #[test]
+    fn test_convert_many_to_one_rejects_bad_chapters() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("book.pdf");
+        let options = ConversionOptions::default();
+
+        let no_inputs: [&Path; 0] = [];
+        let result = convert_many_to_one(&no_inputs, &output, &options);
+        assert!(matches!(result, Err(error::Md2PdfError::NoInputFiles)));
+
+        let intro = dir.path().join("intro.md");
+        std::fs::write(&intro, "# Intro").unwrap();
+        let missing = dir.path().join("missing.md");
+        let result = convert_many_to_one(&[&intro, &missing], &output, &options);
+        assert!(matches!(result, Err(error::Md2PdfError::InvalidPath(path)) if path == missing));
+
+        let empty = dir.path().join("empty.md");
+        std::fs::write(&empty, "\n").unwrap();
+        let err = convert_many_to_one(&[&intro, &empty], &output, &options).unwrap_err();
+        assert!(err.to_string().contains("empty.md"));
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn test_convert_multiple_files_parallel_preserves_order() {
+        let conversions: Vec<_> = (0..7)
+            .map(|i| {
+                (
+                    PathBuf::from(format!("missing-{}.md", i)),
+                    PathBuf::from(format!("missing-{}.pdf", i)),
+                )
+            })
+            .collect();
+
+        let options = ConversionOptions::default();
+        let results = convert_multiple_files_parallel(&conversions, &options, 3);
+
+        assert_eq!(results.len(), conversions.len());
+        for ((input, _), (result, _)) in conversions.iter().zip(&results) {
+            match result {
+                Err(error::Md2PdfError::InvalidPath(path)) => assert_eq!(path, input),
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_catch_panic_reports_the_file() {
+        let input = Path::new("chapter.md");
+        assert!(catch_panic(input, || Ok(())).is_ok());
+
+        match catch_panic(input, || panic!("renderer state lost")) {
+            Err(error::Md2PdfError::ConversionPanicked { path, message }) => {
+                assert_eq!(path, input);
+                assert_eq!(message, "renderer state lost");
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
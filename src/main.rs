@@ -3,12 +3,31 @@
 //! A command-line tool for converting Markdown files to professionally formatted PDFs
 //! with intelligent page break handling.
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use env_logger::Env;
-use log::{error, info, warn};
-use md2pdf::{convert_markdown_to_pdf, convert_multiple_files, ConversionOptions};
+use log::{debug, error, info, warn};
+use md2pdf::config::{self, Config};
+use md2pdf::error::Md2PdfError;
+use md2pdf::html::RawHtml;
+use md2pdf::markdown::{is_stdio, DocumentMetadata};
+use md2pdf::pdf::Renderer;
+use md2pdf::pdf::{parse_length, NetworkPolicy, Orientation, PaperSize};
+use md2pdf::project::Book;
+use md2pdf::serve::{self, PreviewServer};
+use md2pdf::watch::{self, FileWatcher};
+use md2pdf::{
+    convert_book, convert_book_with, convert_many_to_one, convert_many_to_one_with,
+    convert_markdown_to_pdf, convert_markdown_to_pdf_with, convert_multiple_files_parallel,
+    ConversionOptions,
+};
+use std::collections::HashMap;
+use std::ops::ControlFlow;
 use std::path::{Path, PathBuf};
 use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use walkdir::WalkDir;
 
 /// Professional Markdown to PDF converter with smart page breaks
@@ -25,7 +44,7 @@ struct Args {
     /// Input markdown file(s) or directory
     #[arg(
         value_name = "INPUT",
-        help = "Input markdown file(s) or directory containing .md files"
+        help = "Input markdown file(s) or directory containing .md files, or - for stdin"
     )]
     input: Vec<PathBuf>,
 
@@ -34,7 +53,7 @@ struct Args {
         short = 'o',
         long = "output",
         value_name = "OUTPUT",
-        help = "Output PDF file or directory"
+        help = "Output PDF file or directory, or - for stdout"
     )]
     output: Option<PathBuf>,
 
@@ -47,6 +66,13 @@ struct Args {
     )]
     css: Option<PathBuf>,
 
+    /// Ignore configuration files
+    #[arg(
+        long = "no-config",
+        help = "Ignore md2pdf.toml project files and the user configuration file"
+    )]
+    no_config: bool,
+
     /// Enable verbose logging
     #[arg(
         short = 'v',
@@ -55,6 +81,38 @@ struct Args {
     )]
     verbose: bool,
 
+    /// Inline local images as data URIs
+    #[arg(
+        long = "embed-images",
+        overrides_with = "no_embed_images",
+        help = "Inline local images as data URIs for self-contained output"
+    )]
+    embed_images: bool,
+
+    /// Link local images instead of inlining them
+    #[arg(
+        long = "no-embed-images",
+        overrides_with = "embed_images",
+        help = "Link local images instead of inlining them"
+    )]
+    no_embed_images: bool,
+
+    /// Treat recoverable problems as errors
+    #[arg(
+        long = "strict",
+        overrides_with = "no_strict",
+        help = "Fail on recoverable problems such as missing images"
+    )]
+    strict: bool,
+
+    /// Report recoverable problems as warnings
+    #[arg(
+        long = "no-strict",
+        overrides_with = "strict",
+        help = "Only warn about recoverable problems such as missing images"
+    )]
+    no_strict: bool,
+
     /// Process directories recursively
     #[arg(
         short = 'r',
@@ -63,37 +121,356 @@ struct Args {
     )]
     recursive: bool,
 
-    /// Paper width in inches (default: 8.27 for A4)
+    /// Merge all inputs into a single PDF
+    #[arg(
+        long = "merge",
+        help = "Merge all inputs into one PDF, one chapter per file (requires -o unless --manifest is given)"
+    )]
+    merge: bool,
+
+    /// Order of merged chapters
+    #[arg(
+        long = "merge-order",
+        value_name = "ORDER",
+        value_enum,
+        default_value_t = MergeOrder::Cli,
+        help = "Order of merged chapters: as given on the command line, or sorted by path"
+    )]
+    merge_order: MergeOrder,
+
+    /// Book manifest listing the chapters to merge
+    #[arg(
+        long = "manifest",
+        value_name = "FILE",
+        help = "Merge the book described by FILE: a SUMMARY.md, an md2pdf.toml with a [book] table, or a list of files (implies --merge)"
+    )]
+    manifest: Option<PathBuf>,
+
+    /// Rebuild whenever the sources change
+    #[arg(
+        long = "watch",
+        help = "Keep running and rebuild the PDF whenever the markdown, its images or the stylesheet change"
+    )]
+    watch: bool,
+
+    /// Number of files to convert concurrently in batch mode
+    #[arg(
+        short = 'j',
+        long = "jobs",
+        value_name = "N",
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Number of files to convert concurrently in batch mode"
+    )]
+    jobs: u16,
+
+    /// Header template: inline HTML or a file path
+    #[arg(
+        long = "header-template",
+        value_name = "HTML_OR_FILE",
+        help = "Page header template (inline HTML or file path); supports {{pageNumber}}, {{totalPages}}, {{title}}, {{date}} and {{variables}}"
+    )]
+    header_template: Option<String>,
+
+    /// Footer template: inline HTML or a file path
+    #[arg(
+        long = "footer-template",
+        value_name = "HTML_OR_FILE",
+        help = "Page footer template (inline HTML or file path); same placeholders as --header-template"
+    )]
+    footer_template: Option<String>,
+
+    /// Insert a table of contents at the top of the document
+    #[arg(
+        long = "toc",
+        overrides_with = "no_toc",
+        help = "Insert a table of contents (also enabled by a [TOC] marker in the document)"
+    )]
+    toc: bool,
+
+    /// Only insert a table of contents where the document has a [TOC] marker
+    #[arg(
+        long = "no-toc",
+        overrides_with = "toc",
+        help = "Only insert a table of contents at a [TOC] marker"
+    )]
+    no_toc: bool,
+
+    /// Deepest heading level listed in the table of contents
+    #[arg(
+        long = "toc-depth",
+        value_name = "LEVEL",
+        value_parser = clap::value_parser!(u8).range(1..=6),
+        help = "Deepest heading level listed in the table of contents [default: 3]"
+    )]
+    toc_depth: Option<u8>,
+
+    /// Code block highlight theme, or "none" to disable highlighting
+    #[arg(
+        long = "highlight-theme",
+        value_name = "THEME",
+        value_parser = parse_highlight_theme,
+        help = "Code block highlight theme, or \"none\" to disable highlighting [default: InspiredGitHub]"
+    )]
+    highlight_theme: Option<String>,
+
+    /// Pass raw HTML through without sanitizing it
+    #[arg(
+        long = "unsafe-html",
+        conflicts_with = "no_raw_html",
+        help = "Keep raw HTML as written, including scripts (only for trusted input)"
+    )]
+    unsafe_html: bool,
+
+    /// Escape raw HTML so it shows as text
+    #[arg(
+        long = "no-raw-html",
+        help = "Show raw HTML in the markdown as literal text"
+    )]
+    no_raw_html: bool,
+
+    /// Command rendering a diagram language to SVG
+    #[arg(
+        long = "diagram-command",
+        value_name = "LANG=COMMAND",
+        value_parser = parse_variable,
+        help = "Command that reads a diagram from stdin and writes SVG, e.g. dot=\"dot -Tsvg\"; none run unless given (repeatable)"
+    )]
+    diagram_commands: Vec<(String, String)>,
+
+    /// Mermaid library bundle used instead of the bundled build
+    #[arg(
+        long = "mermaid-js",
+        value_name = "FILE",
+        help = "Render mermaid diagrams with this mermaid.min.js instead of the bundled Mermaid"
+    )]
+    mermaid_js: Option<PathBuf>,
+
+    /// Deepest heading level that becomes a PDF bookmark
+    #[arg(
+        long = "outline-depth",
+        value_name = "LEVEL",
+        value_parser = clap::value_parser!(u8).range(0..=6),
+        help = "Deepest heading level turned into a PDF bookmark, 0 disables bookmarks [default: 6]"
+    )]
+    outline_depth: Option<u8>,
+
+    /// Document title (overrides front matter)
+    #[arg(
+        long = "title",
+        value_name = "TITLE",
+        help = "Document title (overrides front matter)"
+    )]
+    title: Option<String>,
+
+    /// Document author (overrides front matter)
+    #[arg(
+        long = "author",
+        value_name = "AUTHOR",
+        help = "Document author (overrides front matter)"
+    )]
+    author: Option<String>,
+
+    /// Document subject (overrides front matter)
+    #[arg(
+        long = "subject",
+        value_name = "SUBJECT",
+        help = "Document subject (overrides front matter)"
+    )]
+    subject: Option<String>,
+
+    /// Comma-separated keywords (overrides front matter)
+    #[arg(
+        long = "keywords",
+        value_name = "KEYWORDS",
+        value_delimiter = ',',
+        help = "Comma-separated document keywords (overrides front matter)"
+    )]
+    keywords: Vec<String>,
+
+    /// Template variables as KEY=VALUE
+    #[arg(
+        long = "var",
+        value_name = "KEY=VALUE",
+        value_parser = parse_variable,
+        help = "Set a variable for header/footer templates, e.g. --var version=1.2"
+    )]
+    variables: Vec<(String, String)>,
+
+    /// Maximum seconds to wait for fonts, images and the ready signal
+    #[arg(
+        long = "render-timeout",
+        value_name = "SECONDS",
+        help = "Maximum seconds to wait for the document to finish rendering [default: 30]"
+    )]
+    render_timeout: Option<f64>,
+
+    /// Wait for the document to set window.md2pdfReady before printing
+    #[arg(
+        long = "wait-for-ready-signal",
+        overrides_with = "no_wait_for_ready_signal",
+        help = "Wait until the document sets window.md2pdfReady before printing"
+    )]
+    wait_for_ready_signal: bool,
+
+    /// Print without waiting for window.md2pdfReady
+    #[arg(
+        long = "no-wait-for-ready-signal",
+        overrides_with = "wait_for_ready_signal",
+        help = "Print without waiting for window.md2pdfReady"
+    )]
+    no_wait_for_ready_signal: bool,
+
+    /// Resources the document may load while rendering
+    #[arg(
+        long = "network",
+        value_name = "POLICY",
+        value_parser = NetworkPolicy::from_str,
+        help = "Resources documents may load: offline, local (files under the input directory) or all [default: local]"
+    )]
+    network: Option<NetworkPolicy>,
+
+    /// Fail when the network policy blocks a request
+    #[arg(
+        long = "fail-on-blocked",
+        overrides_with = "no_fail_on_blocked",
+        help = "Fail the conversion if the network policy blocks any request"
+    )]
+    fail_on_blocked: bool,
+
+    /// Only warn about requests the network policy blocks
+    #[arg(
+        long = "no-fail-on-blocked",
+        overrides_with = "fail_on_blocked",
+        help = "Only warn about requests the network policy blocks"
+    )]
+    no_fail_on_blocked: bool,
+
+    /// Named paper size (default: A4)
+    #[arg(
+        long = "paper",
+        value_name = "SIZE",
+        value_parser = PaperSize::from_str,
+        help = "Paper size: A3, A4, A5, Letter, Legal or Tabloid"
+    )]
+    paper: Option<PaperSize>,
+
+    /// Print in landscape orientation
+    #[arg(
+        long = "landscape",
+        overrides_with = "portrait",
+        help = "Use landscape orientation"
+    )]
+    landscape: bool,
+
+    /// Print in portrait orientation
+    #[arg(
+        long = "portrait",
+        visible_alias = "no-landscape",
+        overrides_with = "landscape",
+        help = "Use portrait orientation even if a configuration file sets landscape"
+    )]
+    portrait: bool,
+
+    /// Print background colors and images
+    #[arg(
+        long = "print-background",
+        overrides_with = "no_print_background",
+        help = "Print background colors and images (the default)"
+    )]
+    print_background: bool,
+
+    /// Leave out background colors and images
+    #[arg(
+        long = "no-print-background",
+        overrides_with = "print_background",
+        help = "Leave out background colors and images"
+    )]
+    no_print_background: bool,
+
+    /// Page rendering scale
+    #[arg(
+        long = "scale",
+        value_name = "SCALE",
+        help = "Page rendering scale, from 0.1 to 2.0 [default: 1.0]"
+    )]
+    scale: Option<f64>,
+
+    /// Paper width (default: 8.27in for A4)
     #[arg(
         long = "paper-width",
         value_name = "WIDTH",
-        help = "Paper width in inches"
+        value_parser = parse_length,
+        help = "Paper width (in, cm, mm or pt; bare numbers are inches)"
     )]
     paper_width: Option<f64>,
 
-    /// Paper height in inches (default: 11.69 for A4)
+    /// Paper height (default: 11.69in for A4)
     #[arg(
         long = "paper-height",
         value_name = "HEIGHT",
-        help = "Paper height in inches"
+        value_parser = parse_length,
+        help = "Paper height (in, cm, mm or pt; bare numbers are inches)"
     )]
     paper_height: Option<f64>,
 
-    /// Top margin in inches
-    #[arg(long = "margin-top", value_name = "MARGIN")]
+    /// Top margin (e.g. 0.5in, 2cm, 15mm, 36pt)
+    #[arg(long = "margin-top", value_name = "MARGIN", value_parser = parse_length)]
     margin_top: Option<f64>,
 
-    /// Bottom margin in inches
-    #[arg(long = "margin-bottom", value_name = "MARGIN")]
+    /// Bottom margin (e.g. 0.5in, 2cm, 15mm, 36pt)
+    #[arg(long = "margin-bottom", value_name = "MARGIN", value_parser = parse_length)]
     margin_bottom: Option<f64>,
 
-    /// Left margin in inches
-    #[arg(long = "margin-left", value_name = "MARGIN")]
+    /// Left margin (e.g. 0.5in, 2cm, 15mm, 36pt)
+    #[arg(long = "margin-left", value_name = "MARGIN", value_parser = parse_length)]
     margin_left: Option<f64>,
 
-    /// Right margin in inches
-    #[arg(long = "margin-right", value_name = "MARGIN")]
+    /// Right margin (e.g. 0.5in, 2cm, 15mm, 36pt)
+    #[arg(long = "margin-right", value_name = "MARGIN", value_parser = parse_length)]
     margin_right: Option<f64>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Inspect configuration files
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Preview the HTML that is printed in a browser, reloading on changes;
+    /// options given before `serve` apply
+    Serve {
+        /// Markdown file to preview
+        #[arg(value_name = "INPUT")]
+        input: PathBuf,
+        /// Port on localhost to serve the preview on
+        #[arg(long = "port", value_name = "PORT", default_value_t = serve::DEFAULT_PORT)]
+        port: u16,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Print the effective configuration, merged from defaults, configuration
+    /// files, front matter and the options given before `config`
+    Show {
+        /// Markdown file whose project file and front matter to include
+        #[arg(value_name = "INPUT")]
+        input: Option<PathBuf>,
+    },
+}
+
+/// Order in which merged inputs become chapters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MergeOrder {
+    /// The order given on the command line
+    Cli,
+    /// Sorted by path
+    Sorted,
 }
 
 fn main() {
@@ -106,43 +483,66 @@ fn main() {
         "md2pdf=info,warn"
     };
 
+    // Logs go to stderr so stdout can carry the PDF
     env_logger::Builder::from_env(Env::default().default_filter_or(filter))
+        .target(env_logger::Target::Stderr)
         .format_timestamp(None)
         .format_module_path(false)
         .format_target(false)
         .init();
 
+    match &args.command {
+        Some(Command::Config {
+            action: ConfigCommand::Show { input },
+        }) => process::exit(show_config(&args, input.as_deref())),
+        Some(Command::Serve { input, port }) => process::exit(serve(&args, input, *port)),
+        None => {}
+    }
+
     // Validate inputs
-    if args.input.is_empty() {
+    if args.input.is_empty() && args.manifest.is_none() {
         error!("No input files provided");
         process::exit(1);
     }
 
-    // Build conversion options
-    let mut options = ConversionOptions {
-        custom_css_path: args.css.as_ref().map(|p| p.to_string_lossy().to_string()),
-        pdf_config: md2pdf::pdf::PdfConfig::default(),
-        verbose: args.verbose,
+    // Configuration files are looked up from the manifest or first input
+    let start = args.manifest.as_deref().or_else(|| {
+        args.input
+            .iter()
+            .map(PathBuf::as_path)
+            .find(|p| !is_stdio(p))
+    });
+    let config_files = if args.no_config {
+        Vec::new()
+    } else {
+        config::config_files(start)
+    };
+    let options = match conversion_options(&args, &config_files) {
+        Ok(options) => options,
+        Err(e) => {
+            error!("{}", e);
+            process::exit(1);
+        }
     };
 
-    // Apply custom PDF configuration if provided
-    if let Some(width) = args.paper_width {
-        options.pdf_config.paper_width = width;
-    }
-    if let Some(height) = args.paper_height {
-        options.pdf_config.paper_height = height;
+    if args.watch {
+        process::exit(watch(&args, &options));
     }
-    if let Some(margin) = args.margin_top {
-        options.pdf_config.margin_top = margin;
-    }
-    if let Some(margin) = args.margin_bottom {
-        options.pdf_config.margin_bottom = margin;
+
+    if args.merge || args.manifest.is_some() {
+        process::exit(merge_files(&args, &options));
     }
-    if let Some(margin) = args.margin_left {
-        options.pdf_config.margin_left = margin;
+
+    // Streams only make sense for a single document
+    let uses_stdio = args.input.iter().any(|input| is_stdio(input))
+        || args.output.as_deref().is_some_and(is_stdio);
+    if uses_stdio && args.input.len() > 1 {
+        error!("'-' (stdin/stdout) can only be used with a single input");
+        process::exit(1);
     }
-    if let Some(margin) = args.margin_right {
-        options.pdf_config.margin_right = margin;
+    if is_stdio(&args.input[0]) && args.output.is_none() {
+        error!("Reading from stdin requires an output path; use -o - to write to stdout");
+        process::exit(1);
     }
 
     // Collect input files
@@ -153,30 +553,267 @@ fn main() {
         process::exit(1);
     }
 
+    if args.output.as_deref().is_some_and(is_stdio)
+        && (input_files.len() > 1 || args.input[0].is_dir())
+    {
+        error!("Writing to stdout (-o -) needs a single input file");
+        process::exit(1);
+    }
+
     info!("Found {} markdown file(s) to convert", input_files.len());
 
     // Determine conversion mode and execute
-    let exit_code = if input_files.len() == 1 && args.output.is_some() {
+    let exit_code = if let (1, Some(output)) = (input_files.len(), args.output.as_ref()) {
         // Single file mode
-        convert_single_file(&input_files[0], args.output.as_ref().unwrap(), &options)
+        convert_single_file(&input_files[0], output, &options)
     } else if input_files.len() > 1 || args.input[0].is_dir() {
         // Batch mode
-        convert_batch(&input_files, args.output.as_deref(), &options)
+        convert_batch(
+            &input_files,
+            &args.input,
+            args.output.as_deref(),
+            &options,
+            usize::from(args.jobs),
+        )
     } else {
         // Single file, auto output
-        let output = PathBuf::from(input_files[0].with_extension("pdf"));
+        let output = input_files[0].with_extension("pdf");
         convert_single_file(&input_files[0], &output, &options)
     };
 
     process::exit(exit_code);
 }
 
+/// Conversion options from the configuration files, overridden by the
+/// command line
+fn conversion_options(
+    args: &Args,
+    config_files: &[PathBuf],
+) -> md2pdf::error::Result<ConversionOptions> {
+    for file in config_files {
+        debug!("Using configuration file: {}", file.display());
+    }
+    let mut options = config::load_options(config_files)?;
+
+    options.verbose = args.verbose;
+    if let Some(css) = &args.css {
+        options.custom_css_path = Some(css.to_string_lossy().to_string());
+    }
+    // A `--flag`/`--no-flag` pair overrides the configuration files only
+    // when one of them was given
+    let flags = [
+        (
+            &mut options.embed_images,
+            flag(args.embed_images, args.no_embed_images),
+        ),
+        (&mut options.strict, flag(args.strict, args.no_strict)),
+        (&mut options.toc, flag(args.toc, args.no_toc)),
+        (
+            &mut options.pdf_config.wait_for_ready_signal,
+            flag(args.wait_for_ready_signal, args.no_wait_for_ready_signal),
+        ),
+        (
+            &mut options.pdf_config.fail_on_blocked_request,
+            flag(args.fail_on_blocked, args.no_fail_on_blocked),
+        ),
+    ];
+    for (option, value) in flags {
+        if let Some(value) = value {
+            *option = value;
+        }
+    }
+    options.variables = args.variables.iter().cloned().collect();
+    options.metadata = DocumentMetadata {
+        title: args.title.clone(),
+        author: args.author.clone(),
+        subject: args.subject.clone(),
+        keywords: args
+            .keywords
+            .iter()
+            .map(|keyword| keyword.trim().to_string())
+            .filter(|keyword| !keyword.is_empty())
+            .collect(),
+        ..Default::default()
+    };
+    if let Some(depth) = args.toc_depth {
+        options.toc_depth = depth;
+    }
+    if let Some(theme) = &args.highlight_theme {
+        options.highlight_theme = (theme != "none").then(|| theme.clone());
+    }
+    if let Some(script) = &args.mermaid_js {
+        options.mermaid_script = Some(script.clone());
+    }
+    if args.unsafe_html {
+        options.raw_html = RawHtml::Allow;
+    } else if args.no_raw_html {
+        options.raw_html = RawHtml::Escape;
+    }
+
+    // An empty command turns rendering off for that diagram language
+    for (language, command) in &args.diagram_commands {
+        if command.trim().is_empty() {
+            options.diagram_commands.remove(language);
+        } else {
+            options
+                .diagram_commands
+                .insert(language.clone(), command.clone());
+        }
+    }
+
+    // Apply custom PDF configuration if provided
+    if let Some(paper) = args.paper {
+        options.pdf_config.paper_size = paper;
+    }
+    if args.paper_width.is_some() || args.paper_height.is_some() {
+        let (width, height) = options.pdf_config.paper_size.dimensions();
+        options.pdf_config.paper_size = PaperSize::Custom {
+            width: args.paper_width.unwrap_or(width),
+            height: args.paper_height.unwrap_or(height),
+        };
+    }
+    if let Some(landscape) = flag(args.landscape, args.portrait) {
+        options.pdf_config.orientation = if landscape {
+            Orientation::Landscape
+        } else {
+            Orientation::Portrait
+        };
+    }
+    if let Some(print_background) = flag(args.print_background, args.no_print_background) {
+        options.pdf_config.print_background = print_background;
+    }
+    if let Some(scale) = args.scale {
+        options.pdf_config.scale = scale;
+    }
+    if let Some(seconds) = args.render_timeout {
+        options.pdf_config.render_timeout = Duration::try_from_secs_f64(seconds).map_err(|_| {
+            Md2PdfError::InvalidPdfConfig(format!("invalid render timeout: {}", seconds))
+        })?;
+    }
+    if let Some(network) = args.network {
+        options.pdf_config.network_policy = network;
+    }
+    if let Some(depth) = args.outline_depth {
+        options.pdf_config.outline_depth = depth;
+    }
+
+    let page_templates = [
+        (
+            &args.header_template,
+            &mut options.pdf_config.header_template,
+        ),
+        (
+            &args.footer_template,
+            &mut options.pdf_config.footer_template,
+        ),
+    ];
+    for (arg, template) in page_templates {
+        if let Some(value) = arg {
+            *template = Some(md2pdf::template::load_page_template(value)?);
+        }
+    }
+    if let Some(margin) = args.margin_top {
+        options.pdf_config.margin_top = margin;
+    }
+    if let Some(margin) = args.margin_bottom {
+        options.pdf_config.margin_bottom = margin;
+    }
+    if let Some(margin) = args.margin_left {
+        options.pdf_config.margin_left = margin;
+    }
+    if let Some(margin) = args.margin_right {
+        options.pdf_config.margin_right = margin;
+    }
+
+    options.pdf_config.validate()?;
+    Ok(options)
+}
+
+/// The value set by a `--flag`/`--no-flag` pair, `None` if neither was given
+fn flag(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (_, true) => Some(false),
+        _ => None,
+    }
+}
+
+/// Print the configuration a conversion of `input` would use
+fn show_config(args: &Args, input: Option<&Path>) -> i32 {
+    let input = input.or_else(|| args.input.first().map(PathBuf::as_path));
+    let config_files = if args.no_config {
+        Vec::new()
+    } else {
+        config::config_files(input)
+    };
+    let options = match conversion_options(args, &config_files) {
+        Ok(options) => options,
+        Err(e) => {
+            error!("{}", e);
+            return 1;
+        }
+    };
+
+    // Front matter sits between the configuration files and the command line
+    let mut metadata = options.metadata_defaults.clone();
+    if let Some(path) = input.filter(|path| path.is_file()) {
+        let front_matter = md2pdf::markdown::read_markdown_file(path)
+            .and_then(|content| Ok(md2pdf::markdown::split_front_matter(&content)?.0));
+        match front_matter {
+            Ok(front_matter) => metadata.merge(&front_matter),
+            Err(e) => {
+                error!("{}", e);
+                return 1;
+            }
+        }
+    }
+    metadata.merge(&options.metadata);
+
+    println!("# Effective configuration, built from (lowest precedence first):");
+    println!("#   built-in defaults");
+    for file in &config_files {
+        println!("#   {}", file.display());
+    }
+    if let Some(path) = input.filter(|path| path.is_file()) {
+        println!("#   front matter of {}", path.display());
+    }
+    println!("#   command line");
+    println!();
+    print!("{}", Config::from_options(&options, &metadata).to_toml());
+    0
+}
+
+/// Parse a `KEY=VALUE` template variable
+fn parse_variable(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.trim().is_empty() => {
+            Ok((key.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected KEY=VALUE, got '{}'", s)),
+    }
+}
+
+/// Check a highlight theme name against the bundled themes
+fn parse_highlight_theme(s: &str) -> Result<String, String> {
+    if s == "none" || md2pdf::highlight::available_themes().contains(&s) {
+        Ok(s.to_string())
+    } else {
+        Err(format!(
+            "unknown theme '{}', expected \"none\" or one of: {}",
+            s,
+            md2pdf::highlight::available_themes().join(", ")
+        ))
+    }
+}
+
 /// Collect all markdown files from input paths
 fn collect_input_files(inputs: &[PathBuf], recursive: bool) -> Vec<PathBuf> {
     let mut files = Vec::new();
 
     for input in inputs {
-        if input.is_file() {
+        if is_stdio(input) {
+            files.push(input.clone());
+        } else if input.is_file() {
             if input
                 .extension()
                 .and_then(|ext| ext.to_str())
@@ -192,7 +829,8 @@ fn collect_input_files(inputs: &[PathBuf], recursive: bool) -> Vec<PathBuf> {
                 WalkDir::new(input).follow_links(true)
             } else {
                 WalkDir::new(input).max_depth(1)
-            };
+            }
+            .sort_by_file_name();
 
             for entry in walker.into_iter().filter_map(|e| e.ok()) {
                 let path = entry.path();
@@ -214,6 +852,287 @@ fn collect_input_files(inputs: &[PathBuf], recursive: bool) -> Vec<PathBuf> {
     files
 }
 
+/// Merge the inputs, or the book described by the manifest, into one PDF
+fn merge_files(args: &Args, options: &ConversionOptions) -> i32 {
+    if let Some(manifest) = &args.manifest {
+        if !args.input.is_empty() {
+            error!("--manifest lists the chapters; don't pass input files as well");
+            return 1;
+        }
+        let book = match Book::load(manifest) {
+            Ok(book) => book,
+            Err(e) => {
+                error!("{}", e);
+                return 1;
+            }
+        };
+        let output = args.output.clone().unwrap_or_else(|| book.output_path());
+        info!(
+            "Converting book {} -> {}",
+            manifest.display(),
+            output.display()
+        );
+        return report_merge(convert_book(&book, &output, options));
+    }
+
+    if args.input.iter().any(|input| is_stdio(input)) {
+        error!("Merging reads files; stdin (-) can't be a chapter");
+        return 1;
+    }
+    let mut input_files = collect_input_files(&args.input, args.recursive);
+    if args.merge_order == MergeOrder::Sorted {
+        input_files.sort();
+    }
+    if input_files.is_empty() {
+        error!("No markdown files found to merge");
+        return 1;
+    }
+    let Some(output) = &args.output else {
+        error!("Merging needs an output file; pass -o <FILE>");
+        return 1;
+    };
+
+    info!(
+        "Merging {} chapter(s) -> {}",
+        input_files.len(),
+        output.display()
+    );
+    report_merge(convert_many_to_one(&input_files, output, options))
+}
+
+/// Log the outcome of a merge and turn it into an exit code
+fn report_merge(result: md2pdf::error::Result<()>) -> i32 {
+    match result {
+        Ok(_) => {
+            info!("Merge successful!");
+            0
+        }
+        Err(e) => {
+            error!("Merge failed: {}", e);
+            1
+        }
+    }
+}
+
+/// The document watch mode rebuilds
+enum WatchTarget {
+    /// A single markdown file
+    Document { input: PathBuf, output: PathBuf },
+    /// Markdown files merged into one PDF
+    Merged {
+        inputs: Vec<PathBuf>,
+        output: PathBuf,
+    },
+    /// A book, reloaded from its manifest on every rebuild
+    Book {
+        manifest: PathBuf,
+        output: Option<PathBuf>,
+    },
+}
+
+impl WatchTarget {
+    fn from_args(args: &Args) -> Result<Self, String> {
+        let uses_stdio = args.input.iter().any(|input| is_stdio(input))
+            || args.output.as_deref().is_some_and(is_stdio);
+        if uses_stdio {
+            return Err("--watch reads and writes files; '-' can't be used".to_string());
+        }
+
+        if let Some(manifest) = &args.manifest {
+            if !args.input.is_empty() {
+                return Err("--manifest lists the chapters; don't pass input files as well".into());
+            }
+            return Ok(Self::Book {
+                manifest: manifest.clone(),
+                output: args.output.clone(),
+            });
+        }
+
+        let mut input_files = collect_input_files(&args.input, args.recursive);
+        if input_files.is_empty() {
+            return Err("No markdown files found in input".to_string());
+        }
+
+        if args.merge {
+            if args.merge_order == MergeOrder::Sorted {
+                input_files.sort();
+            }
+            let Some(output) = args.output.clone() else {
+                return Err("Merging needs an output file; pass -o <FILE>".to_string());
+            };
+            return Ok(Self::Merged {
+                inputs: input_files,
+                output,
+            });
+        }
+
+        if input_files.len() > 1 || args.input[0].is_dir() {
+            return Err(
+                "--watch rebuilds one document; pass a single input file or use --merge"
+                    .to_string(),
+            );
+        }
+        let input = input_files.remove(0);
+        let output = args
+            .output
+            .clone()
+            .unwrap_or_else(|| input.with_extension("pdf"));
+        Ok(Self::Document { input, output })
+    }
+
+    /// Files whose changes trigger a rebuild
+    fn files(&self, options: &ConversionOptions) -> Vec<PathBuf> {
+        match self {
+            Self::Document { input, .. } => watch::document_files(input, options),
+            Self::Merged { inputs, .. } => watch::merged_files(inputs, options),
+            Self::Book { manifest, .. } => match Book::load(manifest) {
+                Ok(book) => watch::book_files(&book, options),
+                // Keep watching the manifest so fixing it triggers a rebuild
+                Err(_) => vec![manifest.clone()],
+            },
+        }
+    }
+
+    /// Convert the document, returning the PDF written
+    fn build(
+        &self,
+        renderer: &Renderer,
+        options: &ConversionOptions,
+    ) -> md2pdf::error::Result<PathBuf> {
+        match self {
+            Self::Document { input, output } => {
+                convert_markdown_to_pdf_with(renderer, input, output, options)?;
+                Ok(output.clone())
+            }
+            Self::Merged { inputs, output } => {
+                convert_many_to_one_with(renderer, inputs, output, options)?;
+                Ok(output.clone())
+            }
+            Self::Book { manifest, output } => {
+                let book = Book::load(manifest)?;
+                let output = output.clone().unwrap_or_else(|| book.output_path());
+                convert_book_with(renderer, &book, &output, options)?;
+                Ok(output)
+            }
+        }
+    }
+}
+
+/// A flag set when Ctrl+C is pressed
+///
+/// Long-running modes stop at the next opportunity instead of being killed,
+/// so the browser is shut down rather than orphaned.
+fn stop_on_ctrl_c() -> Arc<AtomicBool> {
+    let stop = Arc::new(AtomicBool::new(false));
+    let handler_stop = Arc::clone(&stop);
+    if let Err(e) = ctrlc::set_handler(move || handler_stop.store(true, Ordering::SeqCst)) {
+        warn!("Failed to install the Ctrl+C handler: {}", e);
+    }
+    stop
+}
+
+/// Serve a live HTML preview of `input` until interrupted
+fn serve(args: &Args, input: &Path, port: u16) -> i32 {
+    if is_stdio(input) {
+        error!("The preview watches a file; stdin (-) can't be served");
+        return 1;
+    }
+    if !input.is_file() {
+        error!("Input not found: {}", input.display());
+        return 1;
+    }
+
+    let config_files = if args.no_config {
+        Vec::new()
+    } else {
+        config::config_files(Some(input))
+    };
+    let options = match conversion_options(args, &config_files) {
+        Ok(options) => options,
+        Err(e) => {
+            error!("{}", e);
+            return 1;
+        }
+    };
+
+    let server = match PreviewServer::bind(input, &options, port) {
+        Ok(server) => server,
+        Err(e) => {
+            error!("{}", e);
+            return 1;
+        }
+    };
+    info!(
+        "Previewing {} at {}; press Ctrl+C to stop",
+        input.display(),
+        server.url()
+    );
+    server.run(&stop_on_ctrl_c());
+    info!("Stopped the preview server");
+    0
+}
+
+/// Rebuild the PDF whenever one of its sources changes, until interrupted
+///
+/// One renderer is kept for the whole session, so Chrome starts once and a
+/// rebuild only pays for rendering. Failed builds are reported and the
+/// watch goes on.
+fn watch(args: &Args, options: &ConversionOptions) -> i32 {
+    let target = match WatchTarget::from_args(args) {
+        Ok(target) => target,
+        Err(message) => {
+            error!("{}", message);
+            return 1;
+        }
+    };
+
+    let stop = stop_on_ctrl_c();
+    let renderer = Renderer::new();
+    let mut watcher = FileWatcher::new(target.files(options));
+    let mut last_keep_alive = Instant::now();
+    loop {
+        let started = Instant::now();
+        match target.build(&renderer, options) {
+            Ok(output) => info!(
+                "Built {} in {:.2}s",
+                output.display(),
+                started.elapsed().as_secs_f64()
+            ),
+            Err(e) => error!("Build failed: {}", e),
+        }
+
+        // Images may have been added or removed from the document
+        watcher.set_files(target.files(options));
+        info!(
+            "Watching {} file(s) for changes; press Ctrl+C to stop",
+            watcher.files().count()
+        );
+        for path in watcher.files() {
+            debug!("Watching: {}", path.display());
+        }
+
+        let changed = watcher.wait_for_change(|| {
+            if stop.load(Ordering::SeqCst) {
+                return ControlFlow::Break(());
+            }
+            if last_keep_alive.elapsed() >= watch::KEEP_ALIVE_INTERVAL {
+                renderer.keep_alive();
+                last_keep_alive = Instant::now();
+            }
+            ControlFlow::Continue(())
+        });
+        let Some(changed) = changed else {
+            break;
+        };
+        for path in changed {
+            info!("Changed: {}", path.display());
+        }
+    }
+
+    info!("Stopped watching");
+    0
+}
+
 /// Convert a single file
 fn convert_single_file(input: &Path, output: &Path, options: &ConversionOptions) -> i32 {
     info!("Converting: {} -> {}", input.display(), output.display());
@@ -233,8 +1152,10 @@ fn convert_single_file(input: &Path, output: &Path, options: &ConversionOptions)
 /// Convert multiple files in batch mode
 fn convert_batch(
     inputs: &[PathBuf],
+    roots: &[PathBuf],
     output_dir: Option<&Path>,
     options: &ConversionOptions,
+    jobs: usize,
 ) -> i32 {
     // Determine output directory
     let out_dir = match output_dir {
@@ -251,47 +1172,68 @@ fn convert_batch(
     };
 
     // Build conversion list
-    let conversions: Vec<_> = inputs
-        .iter()
-        .map(|input| {
-            let output = out_dir.join(
-                input
-                    .file_name()
-                    .unwrap_or_default()
-                    .to_string_lossy()
-                    .replace(".md", ".pdf"),
-            );
-            (input.clone(), output)
-        })
-        .collect();
+    let conversions = match batch_conversions(inputs, roots, &out_dir) {
+        Ok(conversions) => conversions,
+        Err(message) => {
+            error!("{}", message);
+            return 1;
+        }
+    };
+    for (_, output) in &conversions {
+        if let Some(parent) = output.parent() {
+            if let Err(e) = std::fs::create_dir_all(parent) {
+                error!(
+                    "Failed to create output directory {}: {}",
+                    parent.display(),
+                    e
+                );
+                return 1;
+            }
+        }
+    }
 
     // Execute conversions
-    let results = convert_multiple_files(&conversions, options);
+    let started = Instant::now();
+    let results = convert_multiple_files_parallel(&conversions, options, jobs);
+    let total_elapsed = started.elapsed();
 
     // Report results
     let mut success_count = 0;
     let mut failure_count = 0;
+    let mut file_time = Duration::ZERO;
 
-    for (i, result) in results.iter().enumerate() {
+    for (i, (result, elapsed)) in results.iter().enumerate() {
+        file_time += *elapsed;
         match result {
             Ok(_) => {
                 info!(
-                    "Success: {} -> {}",
+                    "Success: {} -> {} ({:.2}s)",
                     conversions[i].0.display(),
-                    conversions[i].1.display()
+                    conversions[i].1.display(),
+                    elapsed.as_secs_f64()
                 );
                 success_count += 1;
             }
             Err(e) => {
-                error!("Failed: {} - {}", conversions[i].0.display(), e);
+                error!(
+                    "Failed: {} - {} ({:.2}s)",
+                    conversions[i].0.display(),
+                    e,
+                    elapsed.as_secs_f64()
+                );
                 failure_count += 1;
             }
         }
     }
 
+    let average = file_time.as_secs_f64() / results.len().max(1) as f64;
     info!(
-        "Batch conversion completed: {} succeeded, {} failed",
-        success_count, failure_count
+        "Batch conversion completed: {} succeeded, {} failed in {:.2}s ({:.2}s per file, {} job(s))",
+        success_count,
+        failure_count,
+        total_elapsed.as_secs_f64(),
+        average,
+        jobs
     );
 
     if failure_count > 0 {
@@ -300,3 +1242,37 @@ fn convert_batch(
         0
     }
 }
+
+/// Pair each input with its PDF under `out_dir`
+///
+/// Files found in a directory given on the command line keep their path
+/// relative to it, so `docs/a/README.md` and `docs/b/README.md` become
+/// `a/README.pdf` and `b/README.pdf`; other files keep just their name.
+/// Two inputs that would still write the same PDF are an error, since
+/// parallel jobs would race on the file.
+fn batch_conversions(
+    inputs: &[PathBuf],
+    roots: &[PathBuf],
+    out_dir: &Path,
+) -> Result<Vec<(PathBuf, PathBuf)>, String> {
+    let mut written: HashMap<PathBuf, &Path> = HashMap::new();
+    let mut conversions = Vec::with_capacity(inputs.len());
+    for input in inputs {
+        let relative = roots
+            .iter()
+            .filter(|root| root.is_dir())
+            .find_map(|root| input.strip_prefix(root).ok())
+            .unwrap_or_else(|| Path::new(input.file_name().unwrap_or_default()));
+        let output = out_dir.join(relative).with_extension("pdf");
+        if let Some(other) = written.insert(output.clone(), input) {
+            return Err(format!(
+                "{} and {} would both be written to {}",
+                other.display(),
+                input.display(),
+                output.display()
+            ));
+        }
+        conversions.push((input.clone(), output));
+    }
+    Ok(conversions)
+}
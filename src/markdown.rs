@@ -1,13 +1,254 @@
 //! Markdown parsing and validation
 //!
-//! This module handles reading markdown files and validating their content.
+//! This module handles reading markdown files, validating their content and
+//! extracting YAML (`---`) or TOML (`+++`) front matter.
 
 use crate::error::{Md2PdfError, Result};
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
 use std::fs;
+use std::io::{self, Read};
 use std::path::Path;
 
+/// Document metadata taken from front matter
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DocumentMetadata {
+    /// Document title
+    pub title: Option<String>,
+    /// Author, or several authors joined with ", "
+    pub author: Option<String>,
+    /// Date as written in the front matter
+    pub date: Option<String>,
+    /// Subject or short description
+    pub subject: Option<String>,
+    /// Keywords
+    pub keywords: Vec<String>,
+    /// Language tag such as `en` or `de-CH`
+    pub lang: Option<String>,
+    /// Any other scalar keys, converted to strings
+    pub extra: BTreeMap<String, String>,
+}
+
+impl DocumentMetadata {
+    /// Overlay another set of metadata on top of this one
+    ///
+    /// Fields set in `overrides` replace ours; extra keys are merged.
+    pub fn merge(&mut self, overrides: &DocumentMetadata) {
+        let fields = [
+            (&mut self.title, &overrides.title),
+            (&mut self.author, &overrides.author),
+            (&mut self.date, &overrides.date),
+            (&mut self.subject, &overrides.subject),
+            (&mut self.lang, &overrides.lang),
+        ];
+        for (field, value) in fields {
+            if value.is_some() {
+                field.clone_from(value);
+            }
+        }
+        if !overrides.keywords.is_empty() {
+            self.keywords.clone_from(&overrides.keywords);
+        }
+        self.extra
+            .extend(overrides.extra.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+
+    /// All metadata as template variables
+    ///
+    /// Standard fields use their own names (`title`, `author`, `date`,
+    /// `subject`, `keywords`, `lang`); extra keys are included as-is.
+    pub fn variables(&self) -> BTreeMap<String, String> {
+        let mut variables = self.extra.clone();
+        let fields = [
+            ("title", &self.title),
+            ("author", &self.author),
+            ("date", &self.date),
+            ("subject", &self.subject),
+            ("lang", &self.lang),
+        ];
+        for (name, value) in fields {
+            if let Some(value) = value {
+                variables.insert(name.to_string(), value.clone());
+            }
+        }
+        if !self.keywords.is_empty() {
+            variables.insert("keywords".to_string(), self.keywords.join(", "));
+        }
+        variables
+    }
+}
+
+/// Split front matter from the markdown body
+///
+/// Recognizes a YAML block delimited by `---` (closed by `---` or `...`) or a
+/// TOML block delimited by `+++` at the very start of the document. A YAML
+/// block that does not hold a mapping is treated as ordinary markdown.
+/// Documents without front matter get empty metadata and are returned whole.
+pub fn split_front_matter(content: &str) -> Result<(DocumentMetadata, &str)> {
+    let text = content.strip_prefix('\u{feff}').unwrap_or(content);
+
+    let mut lines = text.split_inclusive('\n');
+    let opening = lines.next().unwrap_or_default();
+    let is_toml = match opening.trim_end() {
+        "---" => false,
+        "+++" => true,
+        _ => return Ok((DocumentMetadata::default(), content)),
+    };
+
+    let block_start = opening.len();
+    let mut offset = block_start;
+    for line in lines {
+        let trimmed = line.trim_end();
+        let closes = if is_toml {
+            trimmed == "+++"
+        } else {
+            trimmed == "---" || trimmed == "..."
+        };
+        if closes {
+            let block = &text[block_start..offset];
+            let rest = &text[offset + line.len()..];
+            let mapping = if is_toml {
+                parse_toml(block)?
+            } else {
+                match parse_yaml(block)? {
+                    Some(mapping) => mapping,
+                    None => return Ok((DocumentMetadata::default(), content)),
+                }
+            };
+            return Ok((metadata_from_mapping(mapping), rest));
+        }
+        offset += line.len();
+    }
+
+    // No closing delimiter: not front matter after all
+    Ok((DocumentMetadata::default(), content))
+}
+
+/// Parse a YAML block, returning `None` when it is not a mapping
+fn parse_yaml(block: &str) -> Result<Option<Mapping>> {
+    if block.trim().is_empty() {
+        return Ok(Some(Mapping::new()));
+    }
+    match serde_yaml::from_str::<Value>(block) {
+        Ok(Value::Mapping(mapping)) => Ok(Some(mapping)),
+        Ok(_) => Ok(None),
+        Err(e) => Err(Md2PdfError::FrontMatter(format!("invalid YAML: {}", e))),
+    }
+}
+
+/// Parse a TOML block into the same shape as YAML front matter
+fn parse_toml(block: &str) -> Result<Mapping> {
+    let table: toml::Table = block
+        .parse()
+        .map_err(|e| Md2PdfError::FrontMatter(format!("invalid TOML: {}", e)))?;
+    match toml_to_yaml(toml::Value::Table(table)) {
+        Value::Mapping(mapping) => Ok(mapping),
+        _ => unreachable!("a TOML table converts to a mapping"),
+    }
+}
+
+/// Convert a TOML value to the equivalent YAML value
+fn toml_to_yaml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => Value::Number(i.into()),
+        toml::Value::Float(f) => Value::Number(f.into()),
+        toml::Value::Boolean(b) => Value::Bool(b),
+        toml::Value::Datetime(d) => Value::String(d.to_string()),
+        toml::Value::Array(items) => Value::Sequence(items.into_iter().map(toml_to_yaml).collect()),
+        toml::Value::Table(table) => Value::Mapping(
+            table
+                .into_iter()
+                .map(|(key, value)| (Value::String(key), toml_to_yaml(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Map parsed front matter keys onto document metadata
+fn metadata_from_mapping(mapping: Mapping) -> DocumentMetadata {
+    let mut metadata = DocumentMetadata::default();
+
+    for (key, value) in mapping {
+        let Some(key) = scalar_to_string(&key) else {
+            continue;
+        };
+        match key.to_ascii_lowercase().as_str() {
+            "title" => metadata.title = value_to_string(&value),
+            "author" | "authors" => metadata.author = value_to_string(&value),
+            "date" => metadata.date = value_to_string(&value),
+            "subject" | "description" => metadata.subject = value_to_string(&value),
+            "keywords" | "tags" => metadata.keywords = value_to_list(&value),
+            "lang" | "language" => metadata.lang = value_to_string(&value),
+            _ => {
+                if let Some(text) = value_to_string(&value) {
+                    metadata.extra.insert(key, text);
+                }
+            }
+        }
+    }
+
+    metadata
+}
+
+/// Render a scalar as text
+fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Render a scalar, or a list of scalars joined with ", ", as text
+fn value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Sequence(_) => {
+            let items = value_to_list(value);
+            (!items.is_empty()).then(|| items.join(", "))
+        }
+        _ => scalar_to_string(value),
+    }
+}
+
+/// Read a list of scalars, or a comma-separated string, as a list
+fn value_to_list(value: &Value) -> Vec<String> {
+    match value {
+        Value::Sequence(items) => items.iter().filter_map(scalar_to_string).collect(),
+        Value::String(s) => s
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::to_string)
+            .collect(),
+        _ => scalar_to_string(value).into_iter().collect(),
+    }
+}
+
+/// Path standing for stdin as input, or stdout as output
+pub const STDIO_PATH: &str = "-";
+
+/// Whether `path` is `-`, meaning stdin or stdout rather than a file
+pub fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO_PATH
+}
+
 /// Read and validate a markdown file
+///
+/// The path `-` reads from stdin, which has no extension to check.
 pub fn read_markdown_file(path: &Path) -> Result<String> {
+    if is_stdio(path) {
+        let mut content = String::new();
+        io::stdin()
+            .read_to_string(&mut content)
+            .map_err(|e| Md2PdfError::FileRead {
+                path: path.to_path_buf(),
+                source: e,
+            })?;
+        return Ok(content);
+    }
+
     // Validate file extension
     if !path
         .extension()
@@ -40,6 +281,26 @@ pub fn validate_markdown(content: &str) -> Result<()> {
     Ok(())
 }
 
+/// Escape `text` so markdown shows it literally on one line
+///
+/// Every ASCII punctuation character gets a backslash, which CommonMark
+/// allows for all of them, so emphasis, links, inline HTML and heading
+/// attributes such as `{.class}` stay plain text. Line breaks become spaces.
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\r' | '\n' => escaped.push(' '),
+            c if c.is_ascii_punctuation() => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -52,6 +313,21 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_escape_markdown() {
+        let title = "*Intro* <b>x</b> [link](x.md) {.evil} #1\nnext";
+        let html =
+            crate::html::markdown_to_html(&format!("# {}\n", escape_markdown(title))).unwrap();
+        assert!(
+            html.contains(
+                "<h1 id=\"intro-bxb-linkxmd-evil-1-next\">\
+                 *Intro* &lt;b&gt;x&lt;/b&gt; [link](x.md) {.evil} #1 next</h1>"
+            ),
+            "{}",
+            html
+        );
+    }
+
     #[test]
     fn test_validate_markdown_valid() {
         let result = validate_markdown("# Hello\n\nWorld");
@@ -64,6 +340,13 @@ mod tests {
         assert!(matches!(result, Err(Md2PdfError::InvalidExtension(_))));
     }
 
+    #[test]
+    fn test_is_stdio() {
+        assert!(is_stdio(Path::new("-")));
+        assert!(!is_stdio(Path::new("-.md")));
+        assert!(!is_stdio(Path::new("./-")));
+    }
+
     #[test]
     fn test_read_markdown_file_not_exists() {
         let result = read_markdown_file(Path::new("nonexistent.md"));
@@ -87,4 +370,85 @@ mod tests {
 
         std::fs::remove_file(md_path).unwrap();
     }
+
+    #[test]
+    fn test_split_front_matter_yaml() {
+        let content = "---\ntitle: Design Notes\nauthor: [Ada, Grace]\ndate: 2024-03-01\nkeywords: [rust, pdf]\nlang: en\nversion: 1.2\n---\n# Heading\n";
+        let (metadata, body) = split_front_matter(content).unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("Design Notes"));
+        assert_eq!(metadata.author.as_deref(), Some("Ada, Grace"));
+        assert_eq!(metadata.date.as_deref(), Some("2024-03-01"));
+        assert_eq!(metadata.keywords, vec!["rust", "pdf"]);
+        assert_eq!(metadata.lang.as_deref(), Some("en"));
+        assert_eq!(
+            metadata.extra.get("version").map(String::as_str),
+            Some("1.2")
+        );
+        assert_eq!(body, "# Heading\n");
+    }
+
+    #[test]
+    fn test_split_front_matter_toml() {
+        let content = "+++\r\ntitle = \"Spec\"\nsubject = \"Storage\"\nkeywords = \"a, b\"\ndate = 2024-03-01\n+++\r\nBody";
+        let (metadata, body) = split_front_matter(content).unwrap();
+
+        assert_eq!(metadata.title.as_deref(), Some("Spec"));
+        assert_eq!(metadata.subject.as_deref(), Some("Storage"));
+        assert_eq!(metadata.keywords, vec!["a", "b"]);
+        assert_eq!(metadata.date.as_deref(), Some("2024-03-01"));
+        assert_eq!(body, "Body");
+    }
+
+    #[test]
+    fn test_split_front_matter_absent() {
+        let content = "# Title\n\n---\n\nText";
+        let (metadata, body) = split_front_matter(content).unwrap();
+        assert_eq!(metadata, DocumentMetadata::default());
+        assert_eq!(body, content);
+
+        // An unclosed block or a non-mapping block is plain markdown
+        let content = "---\nJust a rule";
+        assert_eq!(split_front_matter(content).unwrap().1, content);
+        let content = "---\nSetext heading\n---\n";
+        assert_eq!(split_front_matter(content).unwrap().1, content);
+    }
+
+    #[test]
+    fn test_split_front_matter_invalid() {
+        let result = split_front_matter("---\ntitle: [unclosed\n---\n");
+        assert!(matches!(result, Err(Md2PdfError::FrontMatter(_))));
+    }
+
+    #[test]
+    fn test_metadata_merge() {
+        let mut metadata = DocumentMetadata {
+            title: Some("Front matter".to_string()),
+            author: Some("Ada".to_string()),
+            keywords: vec!["a".to_string()],
+            ..Default::default()
+        };
+        metadata.merge(&DocumentMetadata {
+            title: Some("Command line".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(metadata.title.as_deref(), Some("Command line"));
+        assert_eq!(metadata.author.as_deref(), Some("Ada"));
+        assert_eq!(metadata.keywords, vec!["a"]);
+    }
+
+    #[test]
+    fn test_metadata_variables() {
+        let metadata = DocumentMetadata {
+            title: Some("T".to_string()),
+            keywords: vec!["a".to_string(), "b".to_string()],
+            extra: BTreeMap::from([("version".to_string(), "2".to_string())]),
+            ..Default::default()
+        };
+        let variables = metadata.variables();
+        assert_eq!(variables["title"], "T");
+        assert_eq!(variables["keywords"], "a, b");
+        assert_eq!(variables["version"], "2");
+        assert!(!variables.contains_key("author"));
+    }
 }
@@ -4,23 +4,191 @@
 //! which provides excellent CSS support including page break rules.
 
 use crate::error::{Md2PdfError, Result};
-use headless_chrome::{Browser, LaunchOptions};
-use log::{debug, info};
+use crate::html::{directory_url, Heading};
+use crate::markdown::{is_stdio, DocumentMetadata};
+use crate::template::escape_html;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use headless_chrome::browser::tab::RequestPausedDecision;
+use headless_chrome::browser::transport::{SessionId, Transport};
+use headless_chrome::protocol::cdp::Fetch::events::RequestPausedEvent;
+use headless_chrome::protocol::cdp::Fetch::{FailRequest, RequestPattern, RequestStage};
+use headless_chrome::protocol::cdp::Network::ErrorReason;
+use headless_chrome::types::PrintToPdfOptions;
+use headless_chrome::{Browser, LaunchOptions, Tab};
+use log::{debug, info, warn};
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream, StringFormat};
+use std::collections::HashMap;
+use std::fmt;
 use std::fs;
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
+use tempfile::NamedTempFile;
+use url::Url;
+
+/// Paper size, either a named preset or custom dimensions
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum PaperSize {
+    /// ISO A3 (297 x 420 mm)
+    A3,
+    /// ISO A4 (210 x 297 mm)
+    #[default]
+    A4,
+    /// ISO A5 (148 x 210 mm)
+    A5,
+    /// US Letter (8.5 x 11 in)
+    Letter,
+    /// US Legal (8.5 x 14 in)
+    Legal,
+    /// US Tabloid (11 x 17 in)
+    Tabloid,
+    /// Custom dimensions in inches, given in portrait orientation
+    Custom { width: f64, height: f64 },
+}
+
+impl PaperSize {
+    /// Width and height in inches, in portrait orientation
+    pub fn dimensions(&self) -> (f64, f64) {
+        match *self {
+            PaperSize::A3 => (11.69, 16.54),
+            PaperSize::A4 => (8.27, 11.69),
+            PaperSize::A5 => (5.83, 8.27),
+            PaperSize::Letter => (8.5, 11.0),
+            PaperSize::Legal => (8.5, 14.0),
+            PaperSize::Tabloid => (11.0, 17.0),
+            PaperSize::Custom { width, height } => (width, height),
+        }
+    }
+}
+
+impl FromStr for PaperSize {
+    type Err = Md2PdfError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "a3" => Ok(PaperSize::A3),
+            "a4" => Ok(PaperSize::A4),
+            "a5" => Ok(PaperSize::A5),
+            "letter" => Ok(PaperSize::Letter),
+            "legal" => Ok(PaperSize::Legal),
+            "tabloid" => Ok(PaperSize::Tabloid),
+            _ => Err(Md2PdfError::InvalidPdfConfig(format!(
+                "unknown paper size '{}' (expected A3, A4, A5, Letter, Legal or Tabloid)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for PaperSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PaperSize::A3 => write!(f, "A3"),
+            PaperSize::A4 => write!(f, "A4"),
+            PaperSize::A5 => write!(f, "A5"),
+            PaperSize::Letter => write!(f, "Letter"),
+            PaperSize::Legal => write!(f, "Legal"),
+            PaperSize::Tabloid => write!(f, "Tabloid"),
+            PaperSize::Custom { width, height } => write!(f, "{}in x {}in", width, height),
+        }
+    }
+}
+
+/// Page orientation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Orientation {
+    #[default]
+    Portrait,
+    Landscape,
+}
+
+impl fmt::Display for Orientation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Orientation::Portrait => write!(f, "portrait"),
+            Orientation::Landscape => write!(f, "landscape"),
+        }
+    }
+}
+
+/// Which resources a document may load while it renders
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NetworkPolicy {
+    /// Only the document itself and inline `data:` resources
+    Offline,
+    /// Also local files under the input file's directory
+    #[default]
+    LocalFiles,
+    /// Anything, local or remote
+    AllowAll,
+}
+
+impl FromStr for NetworkPolicy {
+    type Err = Md2PdfError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "offline" | "none" => Ok(NetworkPolicy::Offline),
+            "local" => Ok(NetworkPolicy::LocalFiles),
+            "all" => Ok(NetworkPolicy::AllowAll),
+            _ => Err(Md2PdfError::InvalidPdfConfig(format!(
+                "unknown network policy '{}' (expected offline, local or all)",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for NetworkPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetworkPolicy::Offline => write!(f, "offline"),
+            NetworkPolicy::LocalFiles => write!(f, "local"),
+            NetworkPolicy::AllowAll => write!(f, "all"),
+        }
+    }
+}
+
+/// Parse a length such as `2cm`, `15mm`, `0.5in` or `36pt` into inches
+///
+/// A bare number is interpreted as inches.
+pub fn parse_length(s: &str) -> Result<f64> {
+    let trimmed = s.trim();
+    let (number, units_per_inch) = [("in", 1.0), ("cm", 2.54), ("mm", 25.4), ("pt", 72.0)]
+        .iter()
+        .find_map(|(suffix, factor)| {
+            trimmed
+                .strip_suffix(suffix)
+                .map(|number| (number.trim_end(), *factor))
+        })
+        .unwrap_or((trimmed, 1.0));
+
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|value| value.is_finite())
+        .map(|value| value / units_per_inch)
+        .ok_or_else(|| {
+            Md2PdfError::InvalidPdfConfig(format!(
+                "invalid length '{}' (expected a number with an optional in, cm, mm or pt unit)",
+                s
+            ))
+        })
+}
 
 /// PDF generation configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PdfConfig {
     /// Display header and footer
     pub display_header_footer: bool,
     /// Print background graphics
     pub print_background: bool,
-    /// Paper width in inches (8.27 = A4)
-    pub paper_width: f64,
-    /// Paper height in inches (11.69 = A4)
-    pub paper_height: f64,
+    /// Paper size (A4 by default)
+    pub paper_size: PaperSize,
+    /// Page orientation
+    pub orientation: Orientation,
     /// Top margin in inches
     pub margin_top: f64,
     /// Bottom margin in inches
@@ -31,6 +199,20 @@ pub struct PdfConfig {
     pub margin_right: f64,
     /// Scale of the webpage rendering (1.0 = 100%)
     pub scale: f64,
+    /// Maximum time to wait for fonts, images and the ready signal
+    pub render_timeout: Duration,
+    /// Wait for the document to set `window.md2pdfReady` before printing
+    pub wait_for_ready_signal: bool,
+    /// HTML template for the page header (see [`crate::template::expand_page_template`])
+    pub header_template: Option<String>,
+    /// HTML template for the page footer (see [`crate::template::expand_page_template`])
+    pub footer_template: Option<String>,
+    /// Deepest heading level turned into a PDF bookmark (0 disables bookmarks)
+    pub outline_depth: u8,
+    /// Resources the document may load while rendering
+    pub network_policy: NetworkPolicy,
+    /// Fail the conversion if the network policy blocked any request
+    pub fail_on_blocked_request: bool,
 }
 
 impl Default for PdfConfig {
@@ -38,36 +220,357 @@ impl Default for PdfConfig {
         Self {
             display_header_footer: false,
             print_background: true,
-            paper_width: 8.27,  // A4 width
-            paper_height: 11.69, // A4 height
-            margin_top: 0.4,
-            margin_bottom: 0.4,
-            margin_left: 0.4,
-            margin_right: 0.4,
+            paper_size: PaperSize::A4,
+            orientation: Orientation::Portrait,
+            // 2.5cm top and bottom, 2cm left and right
+            margin_top: 2.5 / 2.54,
+            margin_bottom: 2.5 / 2.54,
+            margin_left: 2.0 / 2.54,
+            margin_right: 2.0 / 2.54,
             scale: 1.0,
+            render_timeout: Duration::from_secs(30),
+            wait_for_ready_signal: false,
+            header_template: None,
+            footer_template: None,
+            outline_depth: 6,
+            network_policy: NetworkPolicy::LocalFiles,
+            fail_on_blocked_request: false,
         }
     }
 }
 
-/// Generate PDF from HTML content
-pub fn generate_pdf(html: &str, output_path: &Path, _config: &PdfConfig) -> Result<()> {
-    info!("Starting PDF generation for: {}", output_path.display());
+/// Smallest page scale accepted by Chrome's `Page.printToPDF`
+pub const MIN_SCALE: f64 = 0.1;
 
-    // Launch headless Chrome
-    debug!("Launching headless Chrome browser");
-    let browser = launch_browser()?;
+/// Largest page scale accepted by Chrome's `Page.printToPDF`
+pub const MAX_SCALE: f64 = 2.0;
 
-    // Create a new tab
-    debug!("Creating browser tab");
-    let tab = browser
-        .new_tab()
-        .map_err(|e| Md2PdfError::ChromeLaunch(format!("Failed to create tab: {}", e)))?;
+/// Header or footer template that renders nothing
+const EMPTY_PAGE_TEMPLATE: &str = "<span></span>";
 
-    // Navigate to data URL with HTML content
-    debug!("Loading HTML content");
-    let data_url = format!("data:text/html;charset=utf-8,{}", urlencoding::encode(html));
+/// How long the browser connection may stay silent before it is dropped
+///
+/// Readiness checks run as a single awaited call, so the render timeout has
+/// to stay below this.
+const BROWSER_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+impl PdfConfig {
+    /// Effective page width and height in inches, with orientation applied
+    pub fn page_size(&self) -> (f64, f64) {
+        let (width, height) = self.paper_size.dimensions();
+        match self.orientation {
+            Orientation::Portrait => (width, height),
+            Orientation::Landscape => (height, width),
+        }
+    }
+
+    /// Check that the configuration describes a printable page
+    ///
+    /// Rejects non-positive paper dimensions, negative margins, margins that
+    /// leave no printable area, and scales outside Chrome's supported range.
+    pub fn validate(&self) -> Result<()> {
+        let (paper_width, paper_height) = self.page_size();
+        let dimensions = [("paper width", paper_width), ("paper height", paper_height)];
+        for (name, value) in dimensions {
+            if !value.is_finite() || value <= 0.0 {
+                return Err(Md2PdfError::InvalidPdfConfig(format!(
+                    "{} must be greater than zero, got {}",
+                    name, value
+                )));
+            }
+        }
+
+        let margins = [
+            ("top margin", self.margin_top),
+            ("bottom margin", self.margin_bottom),
+            ("left margin", self.margin_left),
+            ("right margin", self.margin_right),
+        ];
+        for (name, value) in margins {
+            if !value.is_finite() || value < 0.0 {
+                return Err(Md2PdfError::InvalidPdfConfig(format!(
+                    "{} must not be negative, got {}",
+                    name, value
+                )));
+            }
+        }
+
+        if self.margin_top + self.margin_bottom >= paper_height {
+            return Err(Md2PdfError::InvalidPdfConfig(format!(
+                "top and bottom margins ({} + {} in) leave no room on a {} in high page",
+                self.margin_top, self.margin_bottom, paper_height
+            )));
+        }
+        if self.margin_left + self.margin_right >= paper_width {
+            return Err(Md2PdfError::InvalidPdfConfig(format!(
+                "left and right margins ({} + {} in) leave no room on a {} in wide page",
+                self.margin_left, self.margin_right, paper_width
+            )));
+        }
+
+        if !(MIN_SCALE..=MAX_SCALE).contains(&self.scale) {
+            return Err(Md2PdfError::InvalidPdfConfig(format!(
+                "scale must be between {} and {}, got {}",
+                MIN_SCALE, MAX_SCALE, self.scale
+            )));
+        }
+
+        if self.render_timeout.is_zero() || self.render_timeout >= BROWSER_IDLE_TIMEOUT {
+            return Err(Md2PdfError::InvalidPdfConfig(format!(
+                "render timeout must be between 0 and {} seconds, got {:?}",
+                BROWSER_IDLE_TIMEOUT.as_secs(),
+                self.render_timeout
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Map the configuration onto Chrome's print options
+    fn to_print_options(&self) -> PrintToPdfOptions {
+        let (paper_width, paper_height) = self.page_size();
+
+        // Supplying either template turns headers and footers on; the other
+        // one is blanked so Chrome's default title/date/URL line stays out
+        let has_template = self.header_template.is_some() || self.footer_template.is_some();
+        let (header_template, footer_template) = if has_template {
+            let blank = || EMPTY_PAGE_TEMPLATE.to_string();
+            (
+                Some(self.header_template.clone().unwrap_or_else(blank)),
+                Some(self.footer_template.clone().unwrap_or_else(blank)),
+            )
+        } else {
+            (None, None)
+        };
+
+        PrintToPdfOptions {
+            display_header_footer: Some(self.display_header_footer || has_template),
+            header_template,
+            footer_template,
+            print_background: Some(self.print_background),
+            scale: Some(self.scale),
+            paper_width: Some(paper_width),
+            paper_height: Some(paper_height),
+            margin_top: Some(self.margin_top),
+            margin_bottom: Some(self.margin_bottom),
+            margin_left: Some(self.margin_left),
+            margin_right: Some(self.margin_right),
+            // The configured paper size wins over any `@page { size }` rule
+            prefer_css_page_size: Some(false),
+            ..Default::default()
+        }
+    }
+}
+
+/// Headless Chrome renderer that can be reused across conversions
+///
+/// Chrome is launched on first use and kept alive for the lifetime of the
+/// renderer. Tabs are returned to an idle pool after each successful render
+/// so batch conversions pay the browser start-up cost only once.
+#[derive(Default)]
+pub struct Renderer {
+    browser: Mutex<Option<Browser>>,
+    idle_tabs: Mutex<Vec<Arc<Tab>>>,
+}
+
+impl Renderer {
+    /// Create a renderer; Chrome is not launched until the first render
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Render HTML to PDF bytes
+    ///
+    /// Relative references in the document (images, stylesheets, links) are
+    /// resolved against `base_dir` when it is given.
+    pub fn render_pdf(
+        &self,
+        html: &str,
+        base_dir: Option<&Path>,
+        config: &PdfConfig,
+    ) -> Result<Vec<u8>> {
+        config.validate()?;
+
+        let document = stage_document(html, base_dir)?;
+        let filter = RequestFilter::new(config.network_policy, &document.url, base_dir);
+        let tab = self.acquire_tab()?;
+        let result = restrict_requests(&tab, filter).and_then(|blocked| {
+            let pdf_data = print_document(&tab, &document.url, config)?;
+            let blocked = lock(&blocked);
+            if config.fail_on_blocked_request && !blocked.is_empty() {
+                return Err(Md2PdfError::BlockedRequests(blocked.join(", ")));
+            }
+            Ok(pdf_data)
+        });
+
+        // A tab that failed mid-render may be in an unknown state, so only
+        // healthy tabs go back to the pool
+        match result {
+            Ok(_) => self.release_tab(tab),
+            Err(_) => {
+                let _ = tab.close(false);
+            }
+        }
+
+        result
+    }
+
+    /// Render HTML to a PDF file
+    pub fn generate_pdf(
+        &self,
+        html: &str,
+        base_dir: Option<&Path>,
+        output_path: &Path,
+        config: &PdfConfig,
+    ) -> Result<()> {
+        info!("Starting PDF generation for: {}", output_path.display());
+
+        let pdf_data = self.render_pdf(html, base_dir, config)?;
+        write_pdf(output_path, &pdf_data)
+    }
+
+    /// Load HTML in a browser tab and evaluate a script there
+    ///
+    /// The script may return a promise, which is awaited; its result must be
+    /// a string. Used for work that needs a browser besides printing, such
+    /// as rendering diagrams.
+    pub fn evaluate(&self, html: &str, script: &str) -> Result<String> {
+        let document = stage_document(html, None)?;
+        let filter = RequestFilter::new(NetworkPolicy::Offline, &document.url, None);
+        let tab = self.acquire_tab()?;
+        let result = restrict_requests(&tab, filter)
+            .and_then(|_| evaluate_in_document(&tab, &document.url, script));
+
+        match result {
+            Ok(_) => self.release_tab(tab),
+            Err(_) => {
+                let _ = tab.close(false);
+            }
+        }
+
+        result
+    }
+
+    /// Keep the connection to an idle browser open
+    ///
+    /// Chrome's connection is dropped after a few minutes without traffic,
+    /// so long-running callers such as watch mode call this between renders.
+    /// If the browser has gone away it is forgotten along with its tabs, and
+    /// the next render launches a fresh one.
+    pub fn keep_alive(&self) {
+        let browser = lock(&self.browser).clone();
+        if let Some(browser) = browser {
+            if let Err(e) = browser.get_version() {
+                debug!(
+                    "Browser is no longer reachable ({}); relaunching on next use",
+                    e
+                );
+                lock(&self.browser).take();
+                lock(&self.idle_tabs).clear();
+            }
+        }
+    }
+
+    /// Take an idle tab from the pool or open a new one
+    fn acquire_tab(&self) -> Result<Arc<Tab>> {
+        if let Some(tab) = lock(&self.idle_tabs).pop() {
+            debug!("Reusing idle browser tab");
+            return Ok(tab);
+        }
+
+        let browser = self.browser()?;
+        debug!("Creating browser tab");
+        browser.new_tab().map_err(|e| {
+            // The browser may have exited; relaunch it on the next attempt
+            lock(&self.browser).take();
+            Md2PdfError::ChromeLaunch(format!("Failed to create tab: {}", e))
+        })
+    }
+
+    /// Return a tab to the idle pool
+    fn release_tab(&self, tab: Arc<Tab>) {
+        lock(&self.idle_tabs).push(tab);
+    }
+
+    /// Get the running browser, launching it if needed
+    fn browser(&self) -> Result<Browser> {
+        let mut browser = lock(&self.browser);
+        match browser.as_ref() {
+            Some(browser) => Ok(browser.clone()),
+            None => {
+                debug!("Launching headless Chrome browser");
+                let launched = launch_browser()?;
+                *browser = Some(launched.clone());
+                Ok(launched)
+            }
+        }
+    }
+}
+
+/// Lock a mutex, recovering the data if another thread panicked while holding it
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// HTML document written to disk so Chrome can load it from a `file://` URL
+struct StagedDocument {
+    /// Kept alive until rendering is done; the file is removed on drop
+    _file: NamedTempFile,
+    url: Url,
+}
+
+/// Write HTML to a temporary file, pointing its base URL at `base_dir`
+///
+/// Loading from a file rather than a `data:` URL gives the document a real
+/// origin, so relative assets resolve and size limits on data URLs no longer
+/// apply.
+fn stage_document(html: &str, base_dir: Option<&Path>) -> Result<StagedDocument> {
+    let html = match base_dir {
+        Some(dir) => with_base_href(html, &directory_url(dir)?),
+        None => html.to_string(),
+    };
+
+    let mut file = tempfile::Builder::new()
+        .prefix("md2pdf-")
+        .suffix(".html")
+        .tempfile()
+        .map_err(|e| Md2PdfError::FileWrite {
+            path: std::env::temp_dir(),
+            source: e,
+        })?;
+    file.write_all(html.as_bytes())
+        .map_err(|e| Md2PdfError::FileWrite {
+            path: file.path().to_path_buf(),
+            source: e,
+        })?;
 
-    tab.navigate_to(&data_url)
+    let url = Url::from_file_path(file.path())
+        .map_err(|_| Md2PdfError::InvalidPath(file.path().to_path_buf()))?;
+    debug!("Staged HTML document at {}", url);
+
+    Ok(StagedDocument { _file: file, url })
+}
+
+/// Insert a `<base href>` element at the start of the document head
+fn with_base_href(html: &str, base: &Url) -> String {
+    let base_tag = format!(r#"<base href="{}">"#, base);
+    let head_end = html
+        .to_ascii_lowercase()
+        .find("<head")
+        .and_then(|start| html[start..].find('>').map(|end| start + end + 1));
+
+    match head_end {
+        Some(index) => format!("{}\n    {}{}", &html[..index], base_tag, &html[index..]),
+        None => format!("{}\n{}", base_tag, html),
+    }
+}
+
+/// Load a staged document into a tab and print it to PDF
+fn print_document(tab: &Tab, url: &Url, config: &PdfConfig) -> Result<Vec<u8>> {
+    debug!("Loading HTML content");
+    tab.navigate_to(url.as_str())
         .map_err(|e| Md2PdfError::ChromeNavigation(format!("Navigation failed: {}", e)))?;
 
     // Wait for page to load and render
@@ -75,18 +578,210 @@ pub fn generate_pdf(html: &str, output_path: &Path, _config: &PdfConfig) -> Resu
     tab.wait_until_navigated()
         .map_err(|e| Md2PdfError::ChromeNavigation(format!("Wait failed: {}", e)))?;
 
-    // Give additional time for CSS to apply
-    std::thread::sleep(Duration::from_millis(500));
+    wait_until_ready(tab, config)?;
 
     // Generate PDF
     debug!("Generating PDF with configured options");
-    let pdf_data = tab
-        .print_to_pdf(None)
-        .map_err(|e| Md2PdfError::ChromePdfGeneration(format!("PDF generation failed: {}", e)))?;
+    tab.print_to_pdf(Some(config.to_print_options()))
+        .map_err(|e| Md2PdfError::ChromePdfGeneration(format!("PDF generation failed: {}", e)))
+}
+
+/// Decides which requests a document may make under a [`NetworkPolicy`]
+#[derive(Debug, Clone)]
+struct RequestFilter {
+    policy: NetworkPolicy,
+    /// The staged document, which may always be loaded
+    document: Url,
+    /// Directory local files must be under for [`NetworkPolicy::LocalFiles`]
+    local_root: Option<PathBuf>,
+}
+
+impl RequestFilter {
+    fn new(policy: NetworkPolicy, document: &Url, base_dir: Option<&Path>) -> Self {
+        let local_root = base_dir.map(|dir| {
+            let dir = if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            };
+            dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf())
+        });
+        Self {
+            policy,
+            document: document.clone(),
+            local_root,
+        }
+    }
+
+    /// Whether a request for `url` may go ahead
+    fn allows(&self, url: &str) -> bool {
+        if self.policy == NetworkPolicy::AllowAll {
+            return true;
+        }
+        let Ok(mut url) = Url::parse(url) else {
+            return false;
+        };
+        url.set_fragment(None);
+        if url == self.document || matches!(url.scheme(), "data" | "blob" | "about") {
+            return true;
+        }
+
+        match (self.policy, url.scheme(), &self.local_root) {
+            (NetworkPolicy::LocalFiles, "file", Some(root)) => url
+                .to_file_path()
+                .ok()
+                .and_then(|path| path.canonicalize().ok())
+                .is_some_and(|path| path.starts_with(root)),
+            _ => false,
+        }
+    }
+}
+
+/// Enforce a request filter on a tab, failing and logging every request it
+/// rejects
+///
+/// Returns the list the URLs of blocked requests are collected into. Tabs
+/// are reused, so the previous document's interception is always replaced.
+fn restrict_requests(tab: &Tab, filter: RequestFilter) -> Result<Arc<Mutex<Vec<String>>>> {
+    let blocked = Arc::new(Mutex::new(Vec::new()));
+    let interception_error = |e: anyhow::Error| {
+        Md2PdfError::ChromeNavigation(format!("Request interception failed: {}", e))
+    };
+
+    if filter.policy == NetworkPolicy::AllowAll {
+        tab.disable_fetch().map_err(interception_error)?;
+        return Ok(blocked);
+    }
+
+    let patterns = [RequestPattern {
+        url_pattern: Some("*".to_string()),
+        resource_Type: None,
+        request_stage: Some(RequestStage::Request),
+    }];
+    tab.enable_fetch(Some(&patterns), None)
+        .map_err(interception_error)?;
+
+    let blocked_urls = Arc::clone(&blocked);
+    tab.enable_request_interception(Arc::new(
+        move |_transport: Arc<Transport>, _session: SessionId, event: RequestPausedEvent| {
+            let url = event.params.request.url;
+            if filter.allows(&url) {
+                return RequestPausedDecision::Continue(None);
+            }
+            warn!(
+                "Blocked request under '{}' network policy: {}",
+                filter.policy, url
+            );
+            lock(&blocked_urls).push(url);
+            RequestPausedDecision::Fail(FailRequest {
+                request_id: event.params.request_id,
+                error_reason: ErrorReason::BlockedByClient,
+            })
+        },
+    ))
+    .map_err(interception_error)?;
+
+    Ok(blocked)
+}
+
+/// Load a document and return the string result of a script run in it
+fn evaluate_in_document(tab: &Tab, url: &Url, script: &str) -> Result<String> {
+    tab.navigate_to(url.as_str())
+        .map_err(|e| Md2PdfError::ChromeNavigation(format!("Navigation failed: {}", e)))?;
+    tab.wait_until_navigated()
+        .map_err(|e| Md2PdfError::ChromeNavigation(format!("Wait failed: {}", e)))?;
+
+    let outcome = tab
+        .evaluate(script, true)
+        .map_err(|e| Md2PdfError::ChromeNavigation(format!("Script failed: {}", e)))?;
+    outcome
+        .value
+        .as_ref()
+        .and_then(|value| value.as_str())
+        .map(str::to_string)
+        .ok_or_else(|| {
+            Md2PdfError::ChromeNavigation("Script returned no string result".to_string())
+        })
+}
+
+/// Script that resolves once the page is ready to print
+///
+/// Waits for web fonts, then for every `<img>` to load or fail, then (when
+/// enabled) for the document to set `window.md2pdfReady` to a truthy value or
+/// a promise, and finally for two animation frames so layout has settled.
+/// Resolves to `"ready"`, or to `"timeout:<stage>"` naming the step that was
+/// still pending when the timeout fired.
+fn readiness_script(timeout: Duration, wait_for_signal: bool) -> String {
+    format!(
+        r#"(() => {{
+    let stage = "fonts";
+    const ready = (async () => {{
+        await document.fonts.ready;
+        stage = "images";
+        await Promise.all(Array.from(document.images)
+            .filter((img) => !img.complete)
+            .map((img) => new Promise((resolve) => {{
+                img.addEventListener("load", resolve, {{ once: true }});
+                img.addEventListener("error", resolve, {{ once: true }});
+            }})));
+        if ({wait_for_signal}) {{
+            stage = "ready signal";
+            while (!window.md2pdfReady) {{
+                await new Promise((resolve) => setTimeout(resolve, 50));
+            }}
+            await window.md2pdfReady;
+        }}
+        stage = "layout";
+        await new Promise((resolve) => requestAnimationFrame(() => requestAnimationFrame(resolve)));
+        return "ready";
+    }})();
+    const timeout = new Promise((resolve) => setTimeout(() => resolve("timeout:" + stage), {timeout_ms}));
+    return Promise.race([ready, timeout]);
+}})()"#,
+        wait_for_signal = wait_for_signal,
+        timeout_ms = timeout.as_millis(),
+    )
+}
+
+/// Block until the loaded document reports it is ready to print
+fn wait_until_ready(tab: &Tab, config: &PdfConfig) -> Result<()> {
+    debug!("Waiting for fonts and images to load");
+    let script = readiness_script(config.render_timeout, config.wait_for_ready_signal);
+    let outcome = tab
+        .evaluate(&script, true)
+        .map_err(|e| Md2PdfError::ChromeNavigation(format!("Readiness check failed: {}", e)))?;
 
-    // Write PDF to file
+    match outcome.value.as_ref().and_then(|value| value.as_str()) {
+        Some("ready") => Ok(()),
+        Some(status) => Err(Md2PdfError::RenderTimeout {
+            timeout: config.render_timeout,
+            stage: status.trim_start_matches("timeout:").to_string(),
+        }),
+        None => Err(Md2PdfError::ChromeNavigation(
+            "Readiness check returned no result".to_string(),
+        )),
+    }
+}
+
+/// Generate PDF from HTML content
+///
+/// Launches a dedicated browser for this one document. Use a [`Renderer`]
+/// when converting several documents or when relative assets need a base
+/// directory.
+pub fn generate_pdf(html: &str, output_path: &Path, config: &PdfConfig) -> Result<()> {
+    Renderer::new().generate_pdf(html, None, output_path, config)
+}
+
+/// Write PDF bytes to a file, or to stdout for the path `-`
+pub fn write_pdf(output_path: &Path, pdf_data: &[u8]) -> Result<()> {
     debug!("Writing PDF to: {}", output_path.display());
-    fs::write(output_path, pdf_data).map_err(|e| Md2PdfError::FileWrite {
+    let written = if is_stdio(output_path) {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(pdf_data).and_then(|_| stdout.flush())
+    } else {
+        fs::write(output_path, pdf_data)
+    };
+    written.map_err(|e| Md2PdfError::FileWrite {
         path: output_path.to_path_buf(),
         source: e,
     })?;
@@ -95,6 +790,389 @@ pub fn generate_pdf(html: &str, output_path: &Path, _config: &PdfConfig) -> Resu
     Ok(())
 }
 
+/// Rewrite the document information dictionary and XMP metadata of a PDF
+///
+/// Sets Title, Author, Subject, Keywords, Creator and Producer, uses the
+/// document date as CreationDate when it can be parsed (the current time
+/// otherwise), and records the language in the catalog. Fields missing from
+/// `metadata` are removed rather than left as Chrome stamped them.
+pub fn apply_metadata(pdf_data: &[u8], metadata: &DocumentMetadata) -> Result<Vec<u8>> {
+    debug!("Writing PDF metadata");
+    let mut document = load_pdf(pdf_data)?;
+
+    let now = Utc::now();
+    let created = metadata
+        .date
+        .as_deref()
+        .and_then(parse_document_date)
+        .unwrap_or(now);
+    let producer = format!("md2pdf {}", env!("CARGO_PKG_VERSION"));
+    let keywords = metadata.keywords.join(", ");
+
+    let text_fields = [
+        ("Title", metadata.title.as_deref()),
+        ("Author", metadata.author.as_deref()),
+        ("Subject", metadata.subject.as_deref()),
+        (
+            "Keywords",
+            Some(keywords.as_str()).filter(|k| !k.is_empty()),
+        ),
+    ];
+
+    let mut info = Dictionary::new();
+    for (key, value) in text_fields {
+        if let Some(value) = value {
+            info.set(key, pdf_text_string(value));
+        }
+    }
+    info.set("Creator", pdf_text_string("md2pdf"));
+    info.set("Producer", pdf_text_string(&producer));
+    info.set("CreationDate", pdf_date(&created));
+    info.set("ModDate", pdf_date(&now));
+    if let Ok(stale_id) = document.trailer.get(b"Info").and_then(Object::as_reference) {
+        document.objects.remove(&stale_id);
+    }
+    let info_id = document.add_object(info);
+    document.trailer.set("Info", info_id);
+
+    let xmp = xmp_packet(metadata, &producer, &created, &now);
+    let mut xmp_stream = Stream::new(
+        dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+        xmp.into_bytes(),
+    );
+    // XMP must stay readable by tools that scan for the packet
+    xmp_stream.allows_compression = false;
+    let xmp_id = document.add_object(xmp_stream);
+
+    let catalog = document.catalog_mut().map_err(pdf_error)?;
+    catalog.set("Metadata", xmp_id);
+    if let Some(lang) = &metadata.lang {
+        catalog.set("Lang", pdf_text_string(lang));
+    }
+
+    save_pdf(&mut document)
+}
+
+/// Add a bookmark outline built from the document's headings
+///
+/// Each heading with a level up to `max_depth` becomes a bookmark pointing at
+/// the named destination Chrome created for its id; headings are nested
+/// under the closest preceding heading of a lower level. Headings without a
+/// destination (for example because they were never laid out) are skipped.
+pub fn add_outline(pdf_data: &[u8], headings: &[Heading], max_depth: u8) -> Result<Vec<u8>> {
+    let headings: Vec<&Heading> = headings
+        .iter()
+        .filter(|heading| heading.level <= max_depth)
+        .collect();
+    if headings.is_empty() {
+        return Ok(pdf_data.to_vec());
+    }
+
+    debug!("Writing PDF outline for {} heading(s)", headings.len());
+    let mut document = load_pdf(pdf_data)?;
+    let destinations = named_destinations(&document);
+
+    // Build the tree: each node is (heading, destination, children)
+    let mut nodes: Vec<(&Heading, Object, Vec<usize>)> = Vec::new();
+    let mut roots = Vec::new();
+    let mut stack: Vec<usize> = Vec::new();
+    for heading in headings {
+        let Some(destination) = destinations.get(heading.id.as_bytes()) else {
+            warn!("No PDF destination for heading '{}'", heading.text);
+            continue;
+        };
+        while stack
+            .last()
+            .is_some_and(|&parent| nodes[parent].0.level >= heading.level)
+        {
+            stack.pop();
+        }
+        let index = nodes.len();
+        nodes.push((heading, destination.clone(), Vec::new()));
+        match stack.last() {
+            Some(&parent) => nodes[parent].2.push(index),
+            None => roots.push(index),
+        }
+        stack.push(index);
+    }
+    if roots.is_empty() {
+        return Ok(pdf_data.to_vec());
+    }
+
+    let outlines_id = document.new_object_id();
+    let (first, last, count) = write_outline_items(&mut document, &nodes, &roots, outlines_id);
+    document.objects.insert(
+        outlines_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Outlines",
+            "First" => first,
+            "Last" => last,
+            "Count" => count,
+        }),
+    );
+
+    let catalog = document.catalog_mut().map_err(pdf_error)?;
+    catalog.set("Outlines", outlines_id);
+    catalog.set("PageMode", "UseOutlines");
+
+    save_pdf(&mut document)
+}
+
+/// Write one level of outline items, returning its first and last ids and
+/// the total number of items written
+fn write_outline_items(
+    document: &mut Document,
+    nodes: &[(&Heading, Object, Vec<usize>)],
+    level: &[usize],
+    parent_id: ObjectId,
+) -> (ObjectId, ObjectId, i64) {
+    let ids: Vec<ObjectId> = level.iter().map(|_| document.new_object_id()).collect();
+    let mut count = 0;
+
+    for (position, &index) in level.iter().enumerate() {
+        let (heading, destination, children) = &nodes[index];
+        let mut item = dictionary! {
+            "Title" => pdf_text_string(&heading.text),
+            "Parent" => parent_id,
+            "Dest" => destination.clone(),
+        };
+        if position > 0 {
+            item.set("Prev", ids[position - 1]);
+        }
+        if let Some(&next) = ids.get(position + 1) {
+            item.set("Next", next);
+        }
+        if !children.is_empty() {
+            let (first, last, descendants) =
+                write_outline_items(document, nodes, children, ids[position]);
+            item.set("First", first);
+            item.set("Last", last);
+            item.set("Count", descendants);
+            count += descendants;
+        }
+        document
+            .objects
+            .insert(ids[position], Object::Dictionary(item));
+        count += 1;
+    }
+
+    (ids[0], ids[ids.len() - 1], count)
+}
+
+/// Collect named destinations from the catalog's `/Dests` dictionary and
+/// `/Names` tree, resolved to explicit destination arrays
+fn named_destinations(document: &Document) -> HashMap<Vec<u8>, Object> {
+    let mut destinations = HashMap::new();
+    let Ok(catalog) = document.catalog() else {
+        return destinations;
+    };
+
+    if let Ok(dests) = catalog
+        .get(b"Dests")
+        .and_then(|dests| document.dereference(dests))
+        .and_then(|(_, dests)| dests.as_dict())
+    {
+        for (name, destination) in dests.iter() {
+            if let Some(destination) = explicit_destination(document, destination) {
+                destinations.insert(name.clone(), destination);
+            }
+        }
+    }
+
+    if let Ok(tree) = catalog
+        .get(b"Names")
+        .and_then(|names| document.dereference(names))
+        .and_then(|(_, names)| names.as_dict())
+        .and_then(|names| names.get(b"Dests"))
+        .and_then(|tree| document.dereference(tree))
+        .and_then(|(_, tree)| tree.as_dict())
+    {
+        collect_name_tree(document, tree, &mut destinations);
+    }
+
+    destinations
+}
+
+/// Page number (starting at 1) of every named destination in a PDF
+///
+/// Used to fill table of contents page numbers after a first render, since
+/// Chrome names destinations after the element ids links point to.
+pub fn destination_pages(pdf_data: &[u8]) -> Result<HashMap<String, u32>> {
+    let document = load_pdf(pdf_data)?;
+    let page_numbers: HashMap<ObjectId, u32> = document
+        .get_pages()
+        .into_iter()
+        .map(|(number, id)| (id, number))
+        .collect();
+
+    Ok(named_destinations(&document)
+        .into_iter()
+        .filter_map(|(name, destination)| {
+            let page_id = destination.as_array().ok()?.first()?.as_reference().ok()?;
+            let page = page_numbers.get(&page_id)?;
+            Some((String::from_utf8_lossy(&name).into_owned(), *page))
+        })
+        .collect())
+}
+
+/// Walk a name tree node, collecting its leaves into `destinations`
+fn collect_name_tree(
+    document: &Document,
+    node: &Dictionary,
+    destinations: &mut HashMap<Vec<u8>, Object>,
+) {
+    if let Ok(names) = node.get(b"Names").and_then(Object::as_array) {
+        for pair in names.chunks(2) {
+            if let [Object::String(name, _), destination] = pair {
+                if let Some(destination) = explicit_destination(document, destination) {
+                    destinations.insert(name.clone(), destination);
+                }
+            }
+        }
+    }
+    if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+        for kid in kids {
+            if let Ok((_, Object::Dictionary(kid))) = document.dereference(kid) {
+                collect_name_tree(document, kid, destinations);
+            }
+        }
+    }
+}
+
+/// Resolve a destination to its `[page /XYZ left top zoom]` array form
+fn explicit_destination(document: &Document, destination: &Object) -> Option<Object> {
+    match document.dereference(destination).ok()?.1 {
+        array @ Object::Array(_) => Some(array.clone()),
+        Object::Dictionary(dict) => explicit_destination(document, dict.get(b"D").ok()?),
+        _ => None,
+    }
+}
+
+/// Parse PDF bytes produced by Chrome
+fn load_pdf(pdf_data: &[u8]) -> Result<Document> {
+    Document::load_mem(pdf_data).map_err(pdf_error)
+}
+
+/// Serialize a modified PDF document
+fn save_pdf(document: &mut Document) -> Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    document.save_to(&mut buffer).map_err(pdf_error)?;
+    Ok(buffer)
+}
+
+/// Wrap a failure while reading or writing PDF structure
+fn pdf_error(e: impl fmt::Display) -> Md2PdfError {
+    Md2PdfError::PdfGeneration(format!("PDF post-processing failed: {}", e))
+}
+
+/// Encode text as a PDF text string
+///
+/// ASCII is written as a literal string; anything else as UTF-16BE with a
+/// byte order mark, which every PDF reader understands.
+fn pdf_text_string(text: &str) -> Object {
+    if text.is_ascii() {
+        Object::String(text.as_bytes().to_vec(), StringFormat::Literal)
+    } else {
+        let mut bytes = vec![0xFE, 0xFF];
+        bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes));
+        Object::String(bytes, StringFormat::Hexadecimal)
+    }
+}
+
+/// Format a timestamp as a PDF date string
+fn pdf_date(date: &DateTime<Utc>) -> Object {
+    Object::string_literal(date.format("D:%Y%m%d%H%M%SZ").to_string())
+}
+
+/// Parse a front matter date (`2024-03-01` or RFC 3339)
+fn parse_document_date(date: &str) -> Option<DateTime<Utc>> {
+    let date = date.trim();
+    if let Ok(parsed) = DateTime::parse_from_rfc3339(date) {
+        return Some(parsed.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|day| day.and_hms_opt(0, 0, 0))
+        .map(|midnight| midnight.and_utc())
+}
+
+/// Build an XMP metadata packet mirroring the document information
+fn xmp_packet(
+    metadata: &DocumentMetadata,
+    producer: &str,
+    created: &DateTime<Utc>,
+    modified: &DateTime<Utc>,
+) -> String {
+    let mut properties = String::new();
+    let lang = metadata.lang.as_deref().unwrap_or("x-default");
+
+    if let Some(title) = &metadata.title {
+        properties.push_str(&format!(
+            "<dc:title><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:title>\n",
+            escape_html(title)
+        ));
+    }
+    if let Some(author) = &metadata.author {
+        let authors: String = author
+            .split(", ")
+            .map(|name| format!("<rdf:li>{}</rdf:li>", escape_html(name)))
+            .collect();
+        properties.push_str(&format!(
+            "<dc:creator><rdf:Seq>{}</rdf:Seq></dc:creator>\n",
+            authors
+        ));
+    }
+    if let Some(subject) = &metadata.subject {
+        properties.push_str(&format!(
+            "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n",
+            escape_html(subject)
+        ));
+    }
+    if !metadata.keywords.is_empty() {
+        let subjects: String = metadata
+            .keywords
+            .iter()
+            .map(|keyword| format!("<rdf:li>{}</rdf:li>", escape_html(keyword)))
+            .collect();
+        properties.push_str(&format!(
+            "<dc:subject><rdf:Bag>{}</rdf:Bag></dc:subject>\n<pdf:Keywords>{}</pdf:Keywords>\n",
+            subjects,
+            escape_html(&metadata.keywords.join(", "))
+        ));
+    }
+    if metadata.lang.is_some() {
+        properties.push_str(&format!(
+            "<dc:language><rdf:Bag><rdf:li>{}</rdf:li></rdf:Bag></dc:language>\n",
+            escape_html(lang)
+        ));
+    }
+
+    format!(
+        r#"<?xpacket begin="{bom}" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+<rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+<rdf:Description rdf:about=""
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:pdf="http://ns.adobe.com/pdf/1.3/"
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/">
+<dc:format>application/pdf</dc:format>
+{properties}<pdf:Producer>{producer}</pdf:Producer>
+<xmp:CreatorTool>md2pdf</xmp:CreatorTool>
+<xmp:CreateDate>{created}</xmp:CreateDate>
+<xmp:ModifyDate>{modified}</xmp:ModifyDate>
+<xmp:MetadataDate>{modified}</xmp:MetadataDate>
+</rdf:Description>
+</rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#,
+        bom = '\u{feff}',
+        properties = properties,
+        producer = escape_html(producer),
+        created = created.to_rfc3339_opts(SecondsFormat::Secs, true),
+        modified = modified.to_rfc3339_opts(SecondsFormat::Secs, true),
+    )
+}
+
 /// Launch headless Chrome browser with appropriate options
 fn launch_browser() -> Result<Browser> {
     let launch_options = LaunchOptions {
@@ -103,7 +1181,7 @@ fn launch_browser() -> Result<Browser> {
         enable_gpu: false,
         enable_logging: false,
         window_size: Some((1920, 1080)),
-        idle_browser_timeout: Duration::from_secs(30),
+        idle_browser_timeout: BROWSER_IDLE_TIMEOUT,
         ..Default::default()
     };
 
@@ -112,7 +1190,13 @@ fn launch_browser() -> Result<Browser> {
 }
 
 /// Validate output path and create parent directories if needed
+///
+/// The path `-` (stdout) needs no preparation.
 pub fn prepare_output_path(path: &Path) -> Result<()> {
+    if is_stdio(path) {
+        return Ok(());
+    }
+
     // Ensure output has .pdf extension
     if !path
         .extension()
@@ -143,9 +1227,715 @@ mod tests {
     #[test]
     fn test_pdf_config_default() {
         let config = PdfConfig::default();
-        assert_eq!(config.paper_width, 8.27);
-        assert_eq!(config.paper_height, 11.69);
+        assert_eq!(config.paper_size, PaperSize::A4);
+        assert_eq!(config.orientation, Orientation::Portrait);
+        assert_eq!(config.page_size(), (8.27, 11.69));
         assert!(config.print_background);
+        assert!((config.margin_top - 0.98).abs() < 0.01);
+        assert!((config.margin_bottom - 0.98).abs() < 0.01);
+        assert!((config.margin_left - 0.79).abs() < 0.01);
+        assert!((config.margin_right - 0.79).abs() < 0.01);
+        // Untrusted documents can't reach the network or the rest of the disk
+        assert_eq!(config.network_policy, NetworkPolicy::LocalFiles);
+    }
+
+    #[test]
+    fn test_prepare_output_path_accepts_stdout() {
+        assert!(prepare_output_path(Path::new("-")).is_ok());
+        assert!(prepare_output_path(Path::new("out.txt")).is_err());
+    }
+
+    #[test]
+    fn test_network_policy_from_str() {
+        assert_eq!(
+            "offline".parse::<NetworkPolicy>().unwrap(),
+            NetworkPolicy::Offline
+        );
+        assert_eq!(
+            "Local".parse::<NetworkPolicy>().unwrap(),
+            NetworkPolicy::LocalFiles
+        );
+        assert_eq!(
+            "all".parse::<NetworkPolicy>().unwrap(),
+            NetworkPolicy::AllowAll
+        );
+        assert!("internet".parse::<NetworkPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_request_filter() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("image.png");
+        fs::write(&image, b"png").unwrap();
+        let image_url = Url::from_file_path(image.canonicalize().unwrap()).unwrap();
+        let document = Url::parse("file:///tmp/md2pdf-doc.html").unwrap();
+
+        let offline = RequestFilter::new(NetworkPolicy::Offline, &document, Some(dir.path()));
+        assert!(offline.allows("file:///tmp/md2pdf-doc.html#section"));
+        assert!(offline.allows("data:image/png;base64,AAAA"));
+        assert!(!offline.allows(image_url.as_str()));
+        assert!(!offline.allows("https://example.com/tracker.gif"));
+
+        let local = RequestFilter::new(NetworkPolicy::LocalFiles, &document, Some(dir.path()));
+        assert!(local.allows(image_url.as_str()));
+        assert!(!local.allows("file:///etc/passwd"));
+        assert!(!local.allows(&format!("{}/../../etc/passwd", image_url)));
+        assert!(!local.allows("https://example.com/tracker.gif"));
+
+        let all = RequestFilter::new(NetworkPolicy::AllowAll, &document, None);
+        assert!(all.allows("https://example.com/tracker.gif"));
+    }
+
+    #[test]
+    fn test_paper_size_from_str() {
+        assert_eq!("a3".parse::<PaperSize>().unwrap(), PaperSize::A3);
+        assert_eq!("Letter".parse::<PaperSize>().unwrap(), PaperSize::Letter);
+        assert_eq!("LEGAL".parse::<PaperSize>().unwrap(), PaperSize::Legal);
+        assert_eq!(PaperSize::Tabloid.dimensions(), (11.0, 17.0));
+        assert!(matches!(
+            "B5".parse::<PaperSize>(),
+            Err(Md2PdfError::InvalidPdfConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_landscape_swaps_dimensions() {
+        let config = PdfConfig {
+            paper_size: PaperSize::Letter,
+            orientation: Orientation::Landscape,
+            ..Default::default()
+        };
+        assert_eq!(config.page_size(), (11.0, 8.5));
+        let options = config.to_print_options();
+        assert_eq!(options.paper_width, Some(11.0));
+        assert_eq!(options.paper_height, Some(8.5));
+    }
+
+    #[test]
+    fn test_parse_length_units() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        assert!(close(parse_length("0.5").unwrap(), 0.5));
+        assert!(close(parse_length("0.5in").unwrap(), 0.5));
+        assert!(close(parse_length("2.54cm").unwrap(), 1.0));
+        assert!(close(parse_length("25.4 mm").unwrap(), 1.0));
+        assert!(close(parse_length("36pt").unwrap(), 0.5));
+        assert!(parse_length("2em").is_err());
+        assert!(parse_length("cm").is_err());
+        assert!(parse_length("").is_err());
+    }
+
+    #[test]
+    fn test_pdf_config_default_is_valid() {
+        assert!(PdfConfig::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_pdf_config_rejects_oversized_margins() {
+        let config = PdfConfig {
+            margin_top: 6.0,
+            margin_bottom: 6.0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(Md2PdfError::InvalidPdfConfig(_))
+        ));
+
+        let config = PdfConfig {
+            margin_left: 5.0,
+            margin_right: 4.0,
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(Md2PdfError::InvalidPdfConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_pdf_config_rejects_negative_values() {
+        let config = PdfConfig {
+            margin_left: -0.1,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+
+        let config = PdfConfig {
+            paper_size: PaperSize::Custom {
+                width: 0.0,
+                height: 11.0,
+            },
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_pdf_config_rejects_scale_out_of_range() {
+        for scale in [0.05, 2.5, f64::NAN] {
+            let config = PdfConfig {
+                scale,
+                ..Default::default()
+            };
+            assert!(
+                matches!(config.validate(), Err(Md2PdfError::InvalidPdfConfig(_))),
+                "scale {} should be rejected",
+                scale
+            );
+        }
+    }
+
+    #[test]
+    fn test_pdf_config_to_print_options() {
+        let config = PdfConfig {
+            display_header_footer: true,
+            print_background: false,
+            paper_size: PaperSize::Letter,
+            orientation: Orientation::Portrait,
+            margin_top: 1.0,
+            margin_bottom: 0.5,
+            margin_left: 0.75,
+            margin_right: 0.25,
+            scale: 0.8,
+            ..Default::default()
+        };
+        let options = config.to_print_options();
+        assert_eq!(options.display_header_footer, Some(true));
+        assert_eq!(options.print_background, Some(false));
+        assert_eq!(options.paper_width, Some(8.5));
+        assert_eq!(options.paper_height, Some(11.0));
+        assert_eq!(options.margin_top, Some(1.0));
+        assert_eq!(options.margin_bottom, Some(0.5));
+        assert_eq!(options.margin_left, Some(0.75));
+        assert_eq!(options.margin_right, Some(0.25));
+        assert_eq!(options.scale, Some(0.8));
+        assert_eq!(options.prefer_css_page_size, Some(false));
+    }
+
+    #[test]
+    fn test_footer_template_enables_header_footer() {
+        let config = PdfConfig {
+            footer_template: Some(r#"<span class="pageNumber"></span>"#.to_string()),
+            ..Default::default()
+        };
+        let options = config.to_print_options();
+        assert_eq!(options.display_header_footer, Some(true));
+        assert_eq!(
+            options.header_template.as_deref(),
+            Some(EMPTY_PAGE_TEMPLATE)
+        );
+        assert_eq!(
+            options.footer_template.as_deref(),
+            Some(r#"<span class="pageNumber"></span>"#)
+        );
+
+        let options = PdfConfig::default().to_print_options();
+        assert_eq!(options.display_header_footer, Some(false));
+        assert!(options.header_template.is_none());
+    }
+
+    #[test]
+    fn test_pdf_config_rejects_zero_render_timeout() {
+        let config = PdfConfig {
+            render_timeout: Duration::ZERO,
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.validate(),
+            Err(Md2PdfError::InvalidPdfConfig(_))
+        ));
+    }
+
+    #[test]
+    fn test_readiness_script() {
+        let script = readiness_script(Duration::from_millis(1500), false);
+        assert!(script.contains("document.fonts.ready"));
+        assert!(script.contains("document.images"));
+        assert!(script.contains("if (false)"));
+        assert!(script.contains("1500"));
+
+        let script = readiness_script(Duration::from_secs(2), true);
+        assert!(script.contains("if (true)"));
+        assert!(script.contains("window.md2pdfReady"));
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "chrome-tests"),
+        ignore = "requires a local Chrome/Chromium installation"
+    )]
+    fn test_render_times_out_without_ready_signal() {
+        let config = PdfConfig {
+            render_timeout: Duration::from_millis(500),
+            wait_for_ready_signal: true,
+            ..Default::default()
+        };
+        let result = Renderer::new().render_pdf("<p>never ready</p>", None, &config);
+        match result {
+            Err(Md2PdfError::RenderTimeout { stage, .. }) => assert_eq!(stage, "ready signal"),
+            other => panic!("expected a render timeout, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_generate_pdf_rejects_invalid_config_before_launch() {
+        let config = PdfConfig {
+            scale: 10.0,
+            ..Default::default()
+        };
+        let path = std::env::temp_dir().join("md2pdf-invalid-config.pdf");
+        let result = generate_pdf("<p>x</p>", &path, &config);
+        assert!(matches!(result, Err(Md2PdfError::InvalidPdfConfig(_))));
+    }
+
+    /// Read the MediaBox of the first page, in PostScript points
+    fn first_page_media_box(pdf: &[u8]) -> (f64, f64) {
+        let document = lopdf::Document::load_mem(pdf).unwrap();
+        let (_, page_id) = document.get_pages().into_iter().next().unwrap();
+        let page = document.get_dictionary(page_id).unwrap();
+        let media_box = page.get(b"MediaBox").unwrap().as_array().unwrap();
+        let number = |object: &lopdf::Object| match object {
+            lopdf::Object::Integer(i) => *i as f64,
+            lopdf::Object::Real(r) => *r as f64,
+            other => panic!("unexpected MediaBox entry: {:?}", other),
+        };
+        (
+            number(&media_box[2]) - number(&media_box[0]),
+            number(&media_box[3]) - number(&media_box[1]),
+        )
+    }
+
+    #[test]
+    fn test_print_options_match_paper_media_box() {
+        // Chrome sizes each page's MediaBox from the paper width and height
+        let points = |config: &PdfConfig| {
+            let options = config.to_print_options();
+            (
+                options.paper_width.unwrap() * 72.0,
+                options.paper_height.unwrap() * 72.0,
+            )
+        };
+
+        // An A4 page as Chrome writes it
+        let (width, height) = first_page_media_box(&blank_pdf());
+        let (a4_width, a4_height) = points(&PdfConfig::default());
+        assert!((width - a4_width).abs() < 2.0, "A4 width was {}", a4_width);
+        assert!(
+            (height - a4_height).abs() < 2.0,
+            "A4 height was {}",
+            a4_height
+        );
+
+        let letter = PdfConfig {
+            paper_size: PaperSize::Letter,
+            ..Default::default()
+        };
+        assert_eq!(points(&letter), (612.0, 792.0));
+        let landscape = PdfConfig {
+            orientation: Orientation::Landscape,
+            ..letter
+        };
+        assert_eq!(points(&landscape), (792.0, 612.0));
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "chrome-tests"),
+        ignore = "requires a local Chrome/Chromium installation"
+    )]
+    fn test_generate_pdf_honors_paper_size() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let html = "<html><body><h1>Page size</h1></body></html>";
+
+        let a4 = temp_dir.path().join("a4.pdf");
+        generate_pdf(html, &a4, &PdfConfig::default()).unwrap();
+        let (width, height) = first_page_media_box(&fs::read(&a4).unwrap());
+        assert!((width - 8.27 * 72.0).abs() < 2.0, "width was {}", width);
+        assert!((height - 11.69 * 72.0).abs() < 2.0, "height was {}", height);
+
+        let letter = temp_dir.path().join("letter.pdf");
+        let config = PdfConfig {
+            paper_size: PaperSize::Letter,
+            ..Default::default()
+        };
+        generate_pdf(html, &letter, &config).unwrap();
+        let (width, height) = first_page_media_box(&fs::read(&letter).unwrap());
+        assert!((width - 612.0).abs() < 2.0, "width was {}", width);
+        assert!((height - 792.0).abs() < 2.0, "height was {}", height);
+    }
+
+    #[test]
+    #[cfg_attr(
+        not(feature = "chrome-tests"),
+        ignore = "requires a local Chrome/Chromium installation"
+    )]
+    fn test_renderer_reuses_tabs() {
+        let renderer = Renderer::new();
+        let config = PdfConfig::default();
+        let first = renderer.render_pdf("<p>one</p>", None, &config).unwrap();
+        let second = renderer.render_pdf("<p>two</p>", None, &config).unwrap();
+        assert!(first.starts_with(b"%PDF"));
+        assert!(second.starts_with(b"%PDF"));
+        assert_eq!(lock(&renderer.idle_tabs).len(), 1);
+    }
+
+    #[test]
+    fn test_renderer_does_not_launch_for_invalid_config() {
+        let renderer = Renderer::new();
+        let config = PdfConfig {
+            margin_top: 20.0,
+            ..Default::default()
+        };
+        assert!(renderer.render_pdf("<p>x</p>", None, &config).is_err());
+        assert!(lock(&renderer.browser).is_none());
+    }
+
+    #[test]
+    fn test_with_base_href() {
+        let base = Url::parse("file:///docs/my%20book/").unwrap();
+
+        let html = with_base_href("<html><head><title>T</title></head></html>", &base);
+        assert!(html.contains(
+            r#"<head>
+    <base href="file:///docs/my%20book/"><title>"#
+        ));
+
+        let html = with_base_href(r#"<HEAD lang="en"><title>T</title>"#, &base);
+        assert!(html.starts_with(
+            r#"<HEAD lang="en">
+    <base href="#
+        ));
+
+        let html = with_base_href("<p>fragment</p>", &base);
+        assert!(html.starts_with(r#"<base href="file:///docs/my%20book/">"#));
+    }
+
+    #[test]
+    fn test_stage_document_resolves_relative_to_base_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let html = "<html><head></head><body><img src=\"img/arch.png\"></body></html>";
+
+        let document = stage_document(html, Some(temp_dir.path())).unwrap();
+        assert_eq!(document.url.scheme(), "file");
+
+        let staged = fs::read_to_string(document.url.to_file_path().unwrap()).unwrap();
+        let base = Url::from_directory_path(temp_dir.path().canonicalize().unwrap()).unwrap();
+        assert!(staged.contains(&format!(r#"<base href="{}">"#, base)));
+        assert_eq!(
+            base.join("img/arch.png").unwrap().to_file_path().unwrap(),
+            temp_dir.path().canonicalize().unwrap().join("img/arch.png")
+        );
+    }
+
+    #[test]
+    fn test_stage_document_rejects_missing_base_dir() {
+        let result = stage_document("<p>x</p>", Some(Path::new("/nonexistent/md2pdf/dir")));
+        assert!(matches!(result, Err(Md2PdfError::InvalidPath(_))));
+    }
+
+    /// Build a one-page PDF carrying Chrome-style document information
+    fn blank_pdf() -> Vec<u8> {
+        let mut document = Document::with_version("1.4");
+        let pages_id = document.new_object_id();
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        });
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! {
+            "Type" => "Catalog",
+            "Pages" => pages_id,
+        });
+        let info_id = document.add_object(dictionary! {
+            "Producer" => Object::string_literal("Skia/PDF"),
+            "Title" => Object::string_literal("stale"),
+        });
+        document.trailer.set("Root", catalog_id);
+        document.trailer.set("Info", info_id);
+        save_pdf(&mut document).unwrap()
+    }
+
+    /// Decode a PDF text string (literal ASCII or UTF-16BE with BOM)
+    fn decode_text_string(object: &Object) -> String {
+        let bytes = object.as_str().unwrap();
+        match bytes {
+            [0xFE, 0xFF, rest @ ..] => {
+                let units: Vec<u16> = rest
+                    .chunks(2)
+                    .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                    .collect();
+                String::from_utf16(&units).unwrap()
+            }
+            _ => String::from_utf8(bytes.to_vec()).unwrap(),
+        }
+    }
+
+    fn info_dictionary(document: &Document) -> &Dictionary {
+        let info_id = document
+            .trailer
+            .get(b"Info")
+            .unwrap()
+            .as_reference()
+            .unwrap();
+        document.get_dictionary(info_id).unwrap()
+    }
+
+    /// Add named destinations to a PDF made by [`blank_pdf`]
+    fn with_destinations(pdf: &[u8], names: &[&str]) -> Vec<u8> {
+        let mut document = Document::load_mem(pdf).unwrap();
+        let page_id = *document.get_pages().values().next().unwrap();
+        let mut dests = Dictionary::new();
+        for (i, name) in names.iter().enumerate() {
+            let top = 800 - 50 * i as i64;
+            dests.set(
+                name.as_bytes().to_vec(),
+                vec![
+                    page_id.into(),
+                    "XYZ".into(),
+                    0.into(),
+                    top.into(),
+                    Object::Null,
+                ],
+            );
+        }
+        let dests_id = document.add_object(dests);
+        document.catalog_mut().unwrap().set("Dests", dests_id);
+        save_pdf(&mut document).unwrap()
+    }
+
+    /// `pdf` with `names` as named destinations in a `/Names` tree, split
+    /// over two leaves with `/Limits` like Chrome writes larger documents
+    fn with_destination_tree(pdf: &[u8], names: &[&str]) -> Vec<u8> {
+        let mut document = Document::load_mem(pdf).unwrap();
+        let page_id = *document.get_pages().values().next().unwrap();
+        let mut names = names.to_vec();
+        names.sort_unstable();
+        let leaves: Vec<Object> = names
+            .chunks(names.len().div_ceil(2))
+            .map(|chunk| {
+                let mut pairs = Vec::new();
+                for (i, name) in chunk.iter().enumerate() {
+                    let top = 800 - 50 * i as i64;
+                    let destination = document.add_object(dictionary! {
+                        "D" => vec![
+                            page_id.into(),
+                            "XYZ".into(),
+                            0.into(),
+                            top.into(),
+                            Object::Null,
+                        ],
+                    });
+                    pairs.push(Object::string_literal(*name));
+                    pairs.push(destination.into());
+                }
+                let limits = vec![
+                    Object::string_literal(chunk[0]),
+                    Object::string_literal(chunk[chunk.len() - 1]),
+                ];
+                document
+                    .add_object(dictionary! { "Names" => pairs, "Limits" => limits })
+                    .into()
+            })
+            .collect();
+        let tree = document.add_object(dictionary! { "Kids" => leaves });
+        let names_id = document.add_object(dictionary! { "Dests" => tree });
+        document.catalog_mut().unwrap().set("Names", names_id);
+        save_pdf(&mut document).unwrap()
+    }
+
+    fn heading(level: u8, id: &str) -> Heading {
+        Heading {
+            level,
+            id: id.to_string(),
+            text: id.to_uppercase(),
+        }
+    }
+
+    /// Titles of an outline level and, recursively, their children
+    fn outline_titles(document: &Document, parent: &Dictionary) -> Vec<(String, Vec<String>)> {
+        let mut titles = Vec::new();
+        let mut next = parent.get(b"First").ok().cloned();
+        while let Some(Object::Reference(id)) = next {
+            let item = document.get_dictionary(id).unwrap();
+            let children = outline_titles(document, item)
+                .into_iter()
+                .map(|(title, _)| title)
+                .collect();
+            titles.push((decode_text_string(item.get(b"Title").unwrap()), children));
+            assert!(item.get(b"Dest").unwrap().as_array().is_ok());
+            next = item.get(b"Next").ok().cloned();
+        }
+        titles
+    }
+
+    #[test]
+    fn test_add_outline_nests_headings() {
+        let pdf = with_destinations(
+            &blank_pdf(),
+            &["intro", "setup", "deep", "usage", "skipped"],
+        );
+        let headings = vec![
+            heading(1, "intro"),
+            heading(2, "setup"),
+            heading(4, "deep"),
+            heading(1, "usage"),
+            heading(2, "missing"),
+        ];
+
+        let pdf = add_outline(&pdf, &headings, 6).unwrap();
+        let document = Document::load_mem(&pdf).unwrap();
+        let catalog = document.catalog().unwrap();
+        let outlines_id = catalog.get(b"Outlines").unwrap().as_reference().unwrap();
+        let outlines = document.get_dictionary(outlines_id).unwrap();
+
+        assert_eq!(outlines.get(b"Count").unwrap().as_i64().unwrap(), 4);
+        assert_eq!(
+            outline_titles(&document, outlines),
+            vec![
+                ("INTRO".to_string(), vec!["SETUP".to_string()]),
+                ("USAGE".to_string(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_add_outline_respects_max_depth() {
+        let pdf = with_destinations(&blank_pdf(), &["intro", "setup"]);
+        let headings = vec![heading(1, "intro"), heading(2, "setup")];
+
+        let pdf = add_outline(&pdf, &headings, 1).unwrap();
+        let document = Document::load_mem(&pdf).unwrap();
+        let outlines_id = document
+            .catalog()
+            .unwrap()
+            .get(b"Outlines")
+            .unwrap()
+            .as_reference()
+            .unwrap();
+        let outlines = document.get_dictionary(outlines_id).unwrap();
+        assert_eq!(
+            outline_titles(&document, outlines),
+            vec![("INTRO".to_string(), vec![])]
+        );
+
+        // Depth zero leaves the document untouched
+        let original = blank_pdf();
+        assert_eq!(add_outline(&original, &headings, 0).unwrap(), original);
+    }
+
+    #[test]
+    fn test_add_outline_reads_destination_name_tree() {
+        let pdf = with_destination_tree(&blank_pdf(), &["intro", "setup", "usage"]);
+        let names = named_destinations(&Document::load_mem(&pdf).unwrap());
+        let mut found: Vec<_> = names.keys().map(|name| name.as_slice()).collect();
+        found.sort_unstable();
+        assert_eq!(found, [&b"intro"[..], b"setup", b"usage"]);
+
+        let headings = vec![
+            heading(1, "intro"),
+            heading(2, "setup"),
+            heading(1, "usage"),
+        ];
+        let pdf = add_outline(&pdf, &headings, 6).unwrap();
+        let document = Document::load_mem(&pdf).unwrap();
+        let outlines_id = document
+            .catalog()
+            .unwrap()
+            .get(b"Outlines")
+            .unwrap()
+            .as_reference()
+            .unwrap();
+        let outlines = document.get_dictionary(outlines_id).unwrap();
+        assert_eq!(
+            outline_titles(&document, outlines),
+            vec![
+                ("INTRO".to_string(), vec!["SETUP".to_string()]),
+                ("USAGE".to_string(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_destination_pages() {
+        let pdf = with_destinations(&blank_pdf(), &["intro", "usage"]);
+        let pages = destination_pages(&pdf).unwrap();
+        assert_eq!(pages.get("intro"), Some(&1));
+        assert_eq!(pages.get("usage"), Some(&1));
+        assert_eq!(pages.len(), 2);
+    }
+
+    #[test]
+    fn test_apply_metadata_sets_info_and_xmp() {
+        let metadata = DocumentMetadata {
+            title: Some("Storage Design".to_string()),
+            author: Some("Ada Lovelace, Grökel".to_string()),
+            subject: Some("Notes & plans".to_string()),
+            keywords: vec!["storage".to_string(), "design".to_string()],
+            date: Some("2024-03-01".to_string()),
+            lang: Some("en".to_string()),
+            ..Default::default()
+        };
+
+        let pdf = apply_metadata(&blank_pdf(), &metadata).unwrap();
+        let document = Document::load_mem(&pdf).unwrap();
+        let info = info_dictionary(&document);
+
+        let field = |key: &[u8]| decode_text_string(info.get(key).unwrap());
+        assert_eq!(field(b"Title"), "Storage Design");
+        assert_eq!(field(b"Author"), "Ada Lovelace, Grökel");
+        assert_eq!(field(b"Subject"), "Notes & plans");
+        assert_eq!(field(b"Keywords"), "storage, design");
+        assert_eq!(
+            field(b"Producer"),
+            format!("md2pdf {}", env!("CARGO_PKG_VERSION"))
+        );
+        assert_eq!(field(b"CreationDate"), "D:20240301000000Z");
+
+        let catalog = document.catalog().unwrap();
+        assert_eq!(decode_text_string(catalog.get(b"Lang").unwrap()), "en");
+        let xmp_id = catalog.get(b"Metadata").unwrap().as_reference().unwrap();
+        let xmp_stream = document.get_object(xmp_id).unwrap().as_stream().unwrap();
+        let xmp = String::from_utf8(xmp_stream.content.clone()).unwrap();
+        assert!(xmp.contains(r#"<rdf:li xml:lang="x-default">Storage Design</rdf:li>"#));
+        assert!(xmp.contains("<rdf:li>Ada Lovelace</rdf:li><rdf:li>Grökel</rdf:li>"));
+        assert!(xmp.contains("Notes &amp; plans"));
+        assert!(xmp.contains("<pdf:Keywords>storage, design</pdf:Keywords>"));
+        assert!(xmp.contains("<xmp:CreateDate>2024-03-01T00:00:00Z</xmp:CreateDate>"));
+    }
+
+    #[test]
+    fn test_apply_metadata_drops_stale_fields() {
+        let pdf = apply_metadata(&blank_pdf(), &DocumentMetadata::default()).unwrap();
+        let document = Document::load_mem(&pdf).unwrap();
+        let info = info_dictionary(&document);
+
+        assert!(info.get(b"Title").is_err());
+        assert!(info.get(b"Author").is_err());
+        assert!(decode_text_string(info.get(b"Producer").unwrap()).starts_with("md2pdf "));
+        assert!(info.get(b"CreationDate").is_ok());
+    }
+
+    #[test]
+    fn test_parse_document_date() {
+        let date = parse_document_date("2024-03-01").unwrap();
+        assert_eq!(date.to_rfc3339(), "2024-03-01T00:00:00+00:00");
+        let date = parse_document_date("2024-03-01T10:30:00+02:00").unwrap();
+        assert_eq!(date.to_rfc3339(), "2024-03-01T08:30:00+00:00");
+        assert!(parse_document_date("March 2024").is_none());
+    }
+
+    #[test]
+    fn test_apply_metadata_rejects_invalid_pdf() {
+        let result = apply_metadata(b"not a pdf", &DocumentMetadata::default());
+        assert!(matches!(result, Err(Md2PdfError::PdfGeneration(_))));
     }
 
     #[test]
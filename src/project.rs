@@ -0,0 +1,639 @@
+//! Book projects described by a manifest
+//!
+//! A book is an ordered list of markdown chapters, optionally grouped into
+//! titled parts, with a title page and appendices. It can be described by an
+//! mdBook-style `SUMMARY.md`, by the `[book]` table of an `md2pdf.toml`, or by
+//! a plain list of files, and is converted with [`crate::convert_book`].
+
+use crate::error::{Md2PdfError, Result};
+use crate::markdown;
+use crate::ConversionOptions;
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the project file holding a `[book]` table
+pub const PROJECT_FILE: &str = "md2pdf.toml";
+
+/// Output file name for books that don't name one
+const DEFAULT_OUTPUT: &str = "book.pdf";
+
+/// A markdown file in a book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookChapter {
+    /// Path of the markdown file
+    pub path: PathBuf,
+    /// Stylesheet applied to this chapter only
+    pub css: Option<PathBuf>,
+}
+
+/// A group of chapters, introduced by a page with its title if it has one
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Part {
+    /// Title shown on the part's opening page
+    pub title: Option<String>,
+    /// Chapters in reading order
+    pub chapters: Vec<BookChapter>,
+}
+
+/// The role of a markdown file in a book
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChapterKind {
+    /// Cover page, left out of the table of contents and bookmarks
+    TitlePage,
+    /// Regular chapter
+    Chapter,
+    /// Chapter in the back matter
+    Appendix,
+}
+
+impl ChapterKind {
+    /// Classes of the `<section>` holding a chapter of this kind
+    pub fn class(self) -> &'static str {
+        match self {
+            ChapterKind::TitlePage => "chapter title-page",
+            ChapterKind::Chapter => "chapter",
+            ChapterKind::Appendix => "chapter appendix",
+        }
+    }
+}
+
+/// An item of a book in reading order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BookEntry {
+    /// Page opening a part, showing its title
+    Part(String),
+    /// A markdown file
+    Chapter {
+        path: PathBuf,
+        kind: ChapterKind,
+        css: Option<PathBuf>,
+    },
+}
+
+/// A book: chapters grouped into parts, plus a title page and appendices
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Book {
+    /// Manifest the book was loaded from; relative paths in it are resolved
+    /// against its directory
+    pub manifest: PathBuf,
+    /// Document title, overriding front matter
+    pub title: Option<String>,
+    /// Document author, overriding front matter
+    pub author: Option<String>,
+    /// Markdown file rendered as the cover page
+    pub title_page: Option<PathBuf>,
+    /// Chapters, in parts
+    pub parts: Vec<Part>,
+    /// Chapters following the main matter
+    pub appendices: Vec<BookChapter>,
+    /// Stylesheet for the whole book
+    pub css: Option<PathBuf>,
+    /// Output PDF path
+    pub output: Option<PathBuf>,
+}
+
+/// Layout of an `md2pdf.toml` holding a book
+#[derive(Debug, Deserialize)]
+struct ProjectFile {
+    book: Option<BookTable>,
+}
+
+/// The `[book]` table of an `md2pdf.toml`
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct BookTable {
+    title: Option<String>,
+    author: Option<String>,
+    title_page: Option<PathBuf>,
+    css: Option<PathBuf>,
+    output: Option<PathBuf>,
+    #[serde(default)]
+    chapters: Vec<ChapterSpec>,
+    #[serde(default)]
+    parts: Vec<PartTable>,
+    #[serde(default)]
+    appendices: Vec<ChapterSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PartTable {
+    title: Option<String>,
+    #[serde(default)]
+    chapters: Vec<ChapterSpec>,
+}
+
+/// A chapter given as a bare path or as `{ path = "...", css = "..." }`
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ChapterSpec {
+    Path(PathBuf),
+    Table(ChapterTable),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChapterTable {
+    path: PathBuf,
+    css: Option<PathBuf>,
+}
+
+impl Book {
+    /// Load and validate a book manifest
+    ///
+    /// Files ending in `.toml` are read as an `md2pdf.toml` with a `[book]`
+    /// table, `.md` files as an mdBook-style `SUMMARY.md`, and anything else
+    /// as a list of markdown files, one per line.
+    pub fn load(manifest: &Path) -> Result<Self> {
+        let extension = manifest
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        let book = match extension.as_deref() {
+            Some("toml") => Self::from_toml(&read_manifest_file(manifest)?, manifest)?,
+            Some("md") => Self::from_summary(&read_manifest_file(manifest)?, manifest),
+            _ => Self::from_file_list(manifest)?,
+        };
+        book.validate()?;
+        Ok(book)
+    }
+
+    /// Parse the `[book]` table of an `md2pdf.toml`
+    pub fn from_toml(content: &str, manifest: &Path) -> Result<Self> {
+        let error = |message: String| Md2PdfError::Manifest {
+            path: manifest.to_path_buf(),
+            message,
+        };
+        let project: ProjectFile = toml::from_str(content).map_err(|e| error(e.to_string()))?;
+        let table = project
+            .book
+            .ok_or_else(|| error("missing [book] table".to_string()))?;
+
+        let dir = manifest_dir(manifest);
+        let chapter = |spec: ChapterSpec| match spec {
+            ChapterSpec::Path(path) => BookChapter {
+                path: dir.join(path),
+                css: None,
+            },
+            ChapterSpec::Table(table) => BookChapter {
+                path: dir.join(table.path),
+                css: table.css.map(|css| dir.join(css)),
+            },
+        };
+
+        let mut parts = Vec::new();
+        if !table.chapters.is_empty() {
+            parts.push(Part {
+                title: None,
+                chapters: table.chapters.into_iter().map(chapter).collect(),
+            });
+        }
+        parts.extend(table.parts.into_iter().map(|part| Part {
+            title: part.title,
+            chapters: part.chapters.into_iter().map(chapter).collect(),
+        }));
+
+        Ok(Self {
+            manifest: manifest.to_path_buf(),
+            title: table.title,
+            author: table.author,
+            title_page: table.title_page.map(|path| dir.join(path)),
+            parts,
+            appendices: table.appendices.into_iter().map(chapter).collect(),
+            css: table.css.map(|css| dir.join(css)),
+            output: table.output.map(|path| dir.join(path)),
+        })
+    }
+
+    /// Parse an mdBook-style `SUMMARY.md`
+    ///
+    /// A leading `# Summary` title is skipped and later headings start new
+    /// parts. Links in lists are chapters, at any nesting depth; links before
+    /// the first list are chapters too, while links after it are appendices.
+    /// Draft chapters with an empty link are left out.
+    pub fn from_summary(content: &str, manifest: &Path) -> Self {
+        let dir = manifest_dir(manifest);
+        let mut book = Self {
+            manifest: manifest.to_path_buf(),
+            ..Default::default()
+        };
+
+        let mut list_depth = 0usize;
+        let mut seen_list = false;
+        let mut seen_item = false;
+        let mut heading: Option<String> = None;
+
+        for event in Parser::new(content) {
+            match event {
+                Event::Start(Tag::Heading { .. }) => heading = Some(String::new()),
+                Event::Text(text) | Event::Code(text) => {
+                    if let Some(heading) = &mut heading {
+                        heading.push_str(&text);
+                    }
+                }
+                Event::End(TagEnd::Heading(_)) => {
+                    let title = heading.take().unwrap_or_default().trim().to_string();
+                    if seen_item {
+                        book.parts.push(Part {
+                            title: Some(title),
+                            chapters: Vec::new(),
+                        });
+                    }
+                    seen_item = true;
+                }
+                Event::Start(Tag::List(_)) => {
+                    list_depth += 1;
+                    seen_list = true;
+                }
+                Event::End(TagEnd::List(_)) => list_depth = list_depth.saturating_sub(1),
+                Event::Start(Tag::Link { dest_url, .. }) => {
+                    seen_item = true;
+                    if dest_url.is_empty() {
+                        continue;
+                    }
+                    let chapter = BookChapter {
+                        path: dir.join(&*dest_url),
+                        css: None,
+                    };
+                    if list_depth == 0 && seen_list {
+                        book.appendices.push(chapter);
+                    } else {
+                        if book.parts.is_empty() {
+                            book.parts.push(Part::default());
+                        }
+                        book.parts
+                            .last_mut()
+                            .expect("a part was just added")
+                            .chapters
+                            .push(chapter);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        book
+    }
+
+    /// Read a list of markdown files, one per line
+    pub fn from_file_list(manifest: &Path) -> Result<Self> {
+        let chapters = read_manifest(manifest)?
+            .into_iter()
+            .map(|path| BookChapter { path, css: None })
+            .collect();
+        Ok(Self {
+            manifest: manifest.to_path_buf(),
+            parts: vec![Part {
+                title: None,
+                chapters,
+            }],
+            ..Default::default()
+        })
+    }
+
+    /// Check that the book has chapters and that every file it names exists
+    pub fn validate(&self) -> Result<()> {
+        if self.chapters().next().is_none() {
+            return Err(Md2PdfError::Manifest {
+                path: self.manifest.clone(),
+                message: "no chapters listed".to_string(),
+            });
+        }
+
+        let markdown_files = self
+            .title_page
+            .iter()
+            .chain(self.chapters().map(|chapter| &chapter.path));
+        for path in markdown_files {
+            if !path.is_file() {
+                return Err(Md2PdfError::MissingChapter {
+                    chapter: path.clone(),
+                    manifest: self.manifest.clone(),
+                });
+            }
+        }
+
+        let stylesheets = self
+            .css
+            .iter()
+            .chain(self.chapters().filter_map(|chapter| chapter.css.as_ref()));
+        for css in stylesheets {
+            if !css.is_file() {
+                return Err(Md2PdfError::CssNotFound(css.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Chapters and appendices in reading order, without the title page
+    pub fn chapters(&self) -> impl Iterator<Item = &BookChapter> {
+        self.parts
+            .iter()
+            .flat_map(|part| &part.chapters)
+            .chain(&self.appendices)
+    }
+
+    /// Everything that goes into the document, in reading order
+    pub fn entries(&self) -> Vec<BookEntry> {
+        let chapter = |chapter: &BookChapter, kind| BookEntry::Chapter {
+            path: chapter.path.clone(),
+            kind,
+            css: chapter.css.clone(),
+        };
+
+        let mut entries = Vec::new();
+        if let Some(path) = &self.title_page {
+            entries.push(BookEntry::Chapter {
+                path: path.clone(),
+                kind: ChapterKind::TitlePage,
+                css: None,
+            });
+        }
+        for part in &self.parts {
+            if let Some(title) = &part.title {
+                entries.push(BookEntry::Part(title.clone()));
+            }
+            entries.extend(
+                part.chapters
+                    .iter()
+                    .map(|c| chapter(c, ChapterKind::Chapter)),
+            );
+        }
+        entries.extend(
+            self.appendices
+                .iter()
+                .map(|c| chapter(c, ChapterKind::Appendix)),
+        );
+        entries
+    }
+
+    /// Where the PDF goes unless an output path is given explicitly
+    ///
+    /// Defaults to `book.pdf` next to a `SUMMARY.md` or `md2pdf.toml`, and to
+    /// the list's name with a `.pdf` extension for a plain list of files.
+    pub fn output_path(&self) -> PathBuf {
+        if let Some(output) = &self.output {
+            return output.clone();
+        }
+        let is_list = !self
+            .manifest
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("toml") || ext.eq_ignore_ascii_case("md"));
+        if is_list {
+            self.manifest.with_extension("pdf")
+        } else {
+            manifest_dir(&self.manifest).join(DEFAULT_OUTPUT)
+        }
+    }
+
+    /// Conversion options with the book's title, author and stylesheet
+    ///
+    /// Values already set in `options`, such as ones given on the command
+    /// line, take precedence over the manifest.
+    pub fn conversion_options(&self, options: &ConversionOptions) -> ConversionOptions {
+        let mut options = options.clone();
+        let mut metadata = markdown::DocumentMetadata {
+            title: self.title.clone(),
+            author: self.author.clone(),
+            ..Default::default()
+        };
+        metadata.merge(&options.metadata);
+        options.metadata = metadata;
+        if options.custom_css_path.is_none() {
+            options.custom_css_path = self
+                .css
+                .as_ref()
+                .map(|css| css.to_string_lossy().to_string());
+        }
+        options
+    }
+}
+
+/// Directory relative paths in a manifest are resolved against
+fn manifest_dir(manifest: &Path) -> &Path {
+    manifest.parent().unwrap_or(Path::new(""))
+}
+
+fn read_manifest_file(path: &Path) -> Result<String> {
+    fs::read_to_string(path).map_err(|e| Md2PdfError::FileRead {
+        path: path.to_path_buf(),
+        source: e,
+    })
+}
+
+/// Read a manifest listing markdown files, one path per line
+///
+/// Blank lines and lines starting with `#` are skipped. Relative paths are
+/// relative to the manifest's directory.
+fn read_manifest(path: &Path) -> Result<Vec<PathBuf>> {
+    let content = read_manifest_file(path)?;
+    let dir = manifest_dir(path);
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| dir.join(line))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(path: &str) -> BookChapter {
+        BookChapter {
+            path: PathBuf::from("book").join(path),
+            css: None,
+        }
+    }
+
+    #[test]
+    fn test_from_summary() {
+        let summary = "# Summary\n\n\
+            [Introduction](intro.md)\n\n\
+            # Getting Started\n\n\
+            - [Install](install.md)\n\
+            \x20   - [Linux](install/linux.md)\n\
+            - [Draft]()\n\n\
+            # Reference\n\n\
+            - [CLI](cli.md)\n\n\
+            ---\n\n\
+            [Glossary](glossary.md)\n";
+        let book = Book::from_summary(summary, Path::new("book/SUMMARY.md"));
+
+        assert_eq!(
+            book.parts,
+            vec![
+                Part {
+                    title: None,
+                    chapters: vec![chapter("intro.md")],
+                },
+                Part {
+                    title: Some("Getting Started".to_string()),
+                    chapters: vec![chapter("install.md"), chapter("install/linux.md")],
+                },
+                Part {
+                    title: Some("Reference".to_string()),
+                    chapters: vec![chapter("cli.md")],
+                },
+            ]
+        );
+        assert_eq!(book.appendices, vec![chapter("glossary.md")]);
+        assert_eq!(book.output_path(), PathBuf::from("book/book.pdf"));
+    }
+
+    #[test]
+    fn test_from_toml() {
+        let toml = r#"
+            [book]
+            title = "Handbook"
+            title-page = "cover.md"
+            output = "dist/handbook.pdf"
+            chapters = ["intro.md"]
+            appendices = ["glossary.md"]
+
+            [[book.parts]]
+            title = "Usage"
+            chapters = ["usage.md", { path = "api.md", css = "api.css" }]
+        "#;
+        let book = Book::from_toml(toml, Path::new("book/md2pdf.toml")).unwrap();
+
+        assert_eq!(book.title.as_deref(), Some("Handbook"));
+        assert_eq!(
+            book.entries(),
+            vec![
+                BookEntry::Chapter {
+                    path: PathBuf::from("book/cover.md"),
+                    kind: ChapterKind::TitlePage,
+                    css: None,
+                },
+                BookEntry::Chapter {
+                    path: PathBuf::from("book/intro.md"),
+                    kind: ChapterKind::Chapter,
+                    css: None,
+                },
+                BookEntry::Part("Usage".to_string()),
+                BookEntry::Chapter {
+                    path: PathBuf::from("book/usage.md"),
+                    kind: ChapterKind::Chapter,
+                    css: None,
+                },
+                BookEntry::Chapter {
+                    path: PathBuf::from("book/api.md"),
+                    kind: ChapterKind::Chapter,
+                    css: Some(PathBuf::from("book/api.css")),
+                },
+                BookEntry::Chapter {
+                    path: PathBuf::from("book/glossary.md"),
+                    kind: ChapterKind::Appendix,
+                    css: None,
+                },
+            ]
+        );
+        assert_eq!(book.output_path(), PathBuf::from("book/dist/handbook.pdf"));
+
+        let err = Book::from_toml("[book]\nchapterz = []\n", Path::new("md2pdf.toml"));
+        assert!(matches!(err, Err(Md2PdfError::Manifest { .. })));
+        let err = Book::from_toml("[pdf]\n", Path::new("md2pdf.toml"));
+        assert!(err.unwrap_err().to_string().contains("[book]"));
+    }
+
+    #[test]
+    fn test_load_names_missing_chapter() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("intro.md"), "# Intro").unwrap();
+        let manifest = dir.path().join(PROJECT_FILE);
+        fs::write(
+            &manifest,
+            "[book]\nchapters = [\"intro.md\", \"chapter2.md\"]\n",
+        )
+        .unwrap();
+
+        match Book::load(&manifest) {
+            Err(Md2PdfError::MissingChapter {
+                chapter,
+                manifest: listed_in,
+            }) => {
+                assert_eq!(chapter, dir.path().join("chapter2.md"));
+                assert_eq!(listed_in, manifest);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        fs::write(&manifest, "[book]\n").unwrap();
+        let err = Book::load(&manifest).unwrap_err();
+        assert!(err.to_string().contains("no chapters"));
+    }
+
+    #[test]
+    fn test_book_css_overrides_config_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let user = dir.path().join(crate::config::USER_CONFIG_FILE);
+        fs::write(&user, "css = \"user.css\"\n").unwrap();
+        let options = crate::config::load_options(&[user]).unwrap();
+        assert_eq!(
+            options.css_path(),
+            Some(&*dir.path().join("user.css").to_string_lossy())
+        );
+
+        let book = Book {
+            css: Some(PathBuf::from("book.css")),
+            ..Default::default()
+        };
+        assert_eq!(
+            book.conversion_options(&options).css_path(),
+            Some("book.css")
+        );
+
+        // Without a book stylesheet the configured one still applies
+        let plain = Book::default().conversion_options(&options);
+        assert_eq!(plain.css_path(), options.css_path());
+
+        // The command line still wins over the book
+        let cli = ConversionOptions {
+            custom_css_path: Some("cli.css".to_string()),
+            ..options
+        };
+        assert_eq!(book.conversion_options(&cli).css_path(), Some("cli.css"));
+    }
+
+    #[test]
+    fn test_conversion_options_prefer_explicit_values() {
+        let book = Book {
+            title: Some("Book title".to_string()),
+            author: Some("Book author".to_string()),
+            css: Some(PathBuf::from("book.css")),
+            ..Default::default()
+        };
+        let mut options = ConversionOptions::default();
+        options.metadata.title = Some("CLI title".to_string());
+
+        let options = book.conversion_options(&options);
+        assert_eq!(options.metadata.title.as_deref(), Some("CLI title"));
+        assert_eq!(options.metadata.author.as_deref(), Some("Book author"));
+        assert_eq!(options.custom_css_path.as_deref(), Some("book.css"));
+    }
+
+    #[test]
+    fn test_read_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("book.txt");
+        fs::write(
+            &manifest,
+            "# Handbook\n\nintro.md\n  chapters/setup.md  \n# appendix.md\n",
+        )
+        .unwrap();
+
+        let files = read_manifest(&manifest).unwrap();
+        assert_eq!(
+            files,
+            vec![
+                dir.path().join("intro.md"),
+                dir.path().join("chapters/setup.md")
+            ]
+        );
+    }
+}
@@ -0,0 +1,777 @@
+//! Live HTML preview server
+//!
+//! `md2pdf serve` shows the HTML page a document is printed from in an
+//! ordinary browser, so styles can be worked on without producing PDFs. The
+//! page is served unchanged inside a preview page that applies print styles,
+//! sizes it like the paper and reloads it whenever a source file changes.
+//! The server only listens on localhost and the preview page loads nothing
+//! from elsewhere, so it works offline.
+
+use crate::error::{Md2PdfError, Result};
+use crate::pdf::{NetworkPolicy, Renderer};
+use crate::template::escape_html;
+use crate::watch::{self, FileWatcher};
+use crate::{convert_markdown_to_html_with, ConversionOptions};
+use log::{debug, error, info, warn};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Port the preview is served on unless another one is given
+pub const DEFAULT_PORT: u16 = 3000;
+
+/// URL prefix of the document and the files it references
+const FILES_PREFIX: &str = "/files/";
+
+/// URL the preview page polls to learn about rebuilds; also used in
+/// [`PREVIEW_SCRIPT`]
+const VERSION_PATH: &str = "/__md2pdf/version";
+
+/// How often the listener checks for new connections and the stop flag
+const ACCEPT_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a connection may take to send its request
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest request head accepted, in bytes
+const MAX_REQUEST_HEAD: usize = 16 * 1024;
+
+/// Styles of the preview page around the document
+const PREVIEW_CSS: &str = r#"
+html { background: #525659; }
+body { margin: 0; font: 13px system-ui, sans-serif; }
+header {
+    position: sticky; top: 0; z-index: 1;
+    display: flex; justify-content: space-between; gap: 1em;
+    padding: 6px 12px; background: #323639; color: #f1f1f1;
+}
+#sheet {
+    display: block; margin: 24px auto; border: 0;
+    background: white; box-shadow: 0 2px 8px rgba(0, 0, 0, 0.4);
+}
+"#;
+
+/// Script of the preview page: print emulation, sizing and reloading
+const PREVIEW_SCRIPT: &str = r#"
+"use strict";
+const sheet = document.getElementById("sheet");
+const status = document.getElementById("status");
+let version = sheet.dataset.version;
+
+// A media query list as it evaluates while printing
+function printMedia(mediaText) {
+    return mediaText.split(",").map((query) => {
+        const match = /^\s*(not\s+|only\s+)?(print|screen)\b(.*)$/i.exec(query);
+        if (!match) {
+            return query.trim();
+        }
+        const negated = /^not/i.test(match[1] || "");
+        if (match[2].toLowerCase() === "print") {
+            return (negated ? "not all" : "all") + match[3];
+        }
+        return negated ? "all" : "not all";
+    }).join(", ");
+}
+
+function emulateRules(rules) {
+    for (const rule of rules) {
+        if (rule instanceof CSSMediaRule) {
+            rule.media.mediaText = printMedia(rule.media.mediaText);
+        }
+        if (rule.styleSheet) {
+            emulateSheet(rule.styleSheet);
+        }
+        if (rule.cssRules) {
+            emulateRules(rule.cssRules);
+        }
+    }
+}
+
+function emulateSheet(styleSheet) {
+    try {
+        emulateRules(styleSheet.cssRules);
+    } catch (e) {
+        // Rules of stylesheets from other origins can't be read
+    }
+}
+
+// Apply print styles, and lay the document out in the printable area
+function emulatePrint(doc) {
+    for (const node of doc.querySelectorAll("link[media], style[media]")) {
+        node.media = printMedia(node.media);
+    }
+    for (const styleSheet of doc.styleSheets) {
+        emulateSheet(styleSheet);
+    }
+    doc.documentElement.style.padding = sheet.dataset.margins;
+    doc.documentElement.style.overflow = "hidden";
+}
+
+function fitHeight(doc) {
+    const height = Math.ceil(doc.documentElement.getBoundingClientRect().height);
+    sheet.style.height = height + "px";
+}
+
+sheet.addEventListener("load", () => {
+    const doc = sheet.contentDocument;
+    emulatePrint(doc);
+    fitHeight(doc);
+    new ResizeObserver(() => fitHeight(doc)).observe(doc.documentElement);
+});
+
+function poll() {
+    fetch(sheet.dataset.versionUrl, { cache: "no-store" })
+        .then((response) => response.text())
+        .then((current) => {
+            if (current !== version) {
+                version = current;
+                sheet.contentWindow.location.reload();
+                status.textContent = "Updated " + new Date().toLocaleTimeString();
+            }
+        })
+        .catch(() => {
+            status.textContent = "Disconnected from md2pdf";
+        })
+        .finally(() => setTimeout(poll, 500));
+}
+poll();
+"#;
+
+/// The latest build of the document
+struct Build {
+    /// Incremented on every build, so the preview knows when to reload
+    version: u64,
+    /// The HTML page, or why it could not be built
+    page: std::result::Result<String, String>,
+}
+
+/// A preview of one markdown file, rebuilt whenever its sources change
+///
+/// The preview page at `/` shows the document printed by md2pdf, served
+/// unchanged under `/files/` next to the files it references.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// use md2pdf::serve::{PreviewServer, DEFAULT_PORT};
+/// use md2pdf::ConversionOptions;
+/// use std::path::Path;
+/// use std::sync::atomic::AtomicBool;
+///
+/// let options = ConversionOptions::default();
+/// let server = PreviewServer::bind(Path::new("guide.md"), &options, DEFAULT_PORT)
+///     .expect("Failed to start the server");
+/// println!("Preview at {}", server.url());
+/// server.run(&AtomicBool::new(false));
+/// ```
+pub struct PreviewServer {
+    listener: TcpListener,
+    address: SocketAddr,
+    input: PathBuf,
+    /// Directory relative references resolve against
+    base_dir: PathBuf,
+    /// Name the document is served under in [`FILES_PREFIX`]
+    document_name: String,
+    options: ConversionOptions,
+    renderer: Renderer,
+    build: Mutex<Build>,
+}
+
+impl PreviewServer {
+    /// Listen on `port` of localhost and build the document
+    ///
+    /// Port 0 picks a free port; [`PreviewServer::url`] tells which. A build
+    /// failure is shown in the preview rather than returned.
+    pub fn bind(input: &Path, options: &ConversionOptions, port: u16) -> Result<Self> {
+        let requested = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        let server_error = |source| Md2PdfError::Server {
+            address: requested.to_string(),
+            source,
+        };
+        let listener = TcpListener::bind(requested).map_err(server_error)?;
+        listener.set_nonblocking(true).map_err(server_error)?;
+        let address = listener.local_addr().map_err(server_error)?;
+
+        let base_dir = options
+            .base_dir
+            .clone()
+            .or_else(|| input.parent().map(Path::to_path_buf))
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or_else(|| PathBuf::from("."));
+        let base_dir = base_dir
+            .canonicalize()
+            .map_err(|_| Md2PdfError::InvalidPath(base_dir))?;
+        let document_name = format!(
+            "{}.html",
+            input.file_stem().unwrap_or_default().to_string_lossy()
+        );
+
+        let server = Self {
+            listener,
+            address,
+            input: input.to_path_buf(),
+            base_dir,
+            document_name,
+            options: options.clone(),
+            renderer: Renderer::new(),
+            build: Mutex::new(Build {
+                version: 0,
+                page: Err(String::new()),
+            }),
+        };
+        server.rebuild();
+        Ok(server)
+    }
+
+    /// Address of the preview page
+    pub fn url(&self) -> String {
+        format!("http://{}/", self.address)
+    }
+
+    /// Serve requests, rebuilding the document whenever its sources change,
+    /// until `stop` is set
+    pub fn run(&self, stop: &AtomicBool) {
+        thread::scope(|scope| {
+            scope.spawn(|| self.watch_sources(stop));
+            while !stop.load(Ordering::SeqCst) {
+                match self.listener.accept() {
+                    Ok((stream, peer)) => {
+                        debug!("Connection from {}", peer);
+                        scope.spawn(move || self.handle_connection(stream));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(ACCEPT_INTERVAL)
+                    }
+                    Err(e) => warn!("Failed to accept a connection: {}", e),
+                }
+            }
+        });
+    }
+
+    /// Rebuild the document whenever one of its sources changes
+    fn watch_sources(&self, stop: &AtomicBool) {
+        let files = || watch::document_files(&self.input, &self.options);
+        let mut watcher = FileWatcher::new(files());
+        let mut last_keep_alive = Instant::now();
+        loop {
+            let changed = watcher.wait_for_change(|| {
+                if stop.load(Ordering::SeqCst) {
+                    return ControlFlow::Break(());
+                }
+                // Diagrams may have started a browser worth keeping
+                if last_keep_alive.elapsed() >= watch::KEEP_ALIVE_INTERVAL {
+                    self.renderer.keep_alive();
+                    last_keep_alive = Instant::now();
+                }
+                ControlFlow::Continue(())
+            });
+            let Some(changed) = changed else {
+                return;
+            };
+            for path in changed {
+                info!("Changed: {}", path.display());
+            }
+            self.rebuild();
+            watcher.set_files(files());
+        }
+    }
+
+    /// Convert the document and publish the result as a new version
+    fn rebuild(&self) {
+        let started = Instant::now();
+        let page = convert_markdown_to_html_with(&self.renderer, &self.input, &self.options);
+        match &page {
+            Ok(_) => info!(
+                "Built {} in {:.2}s",
+                self.input.display(),
+                started.elapsed().as_secs_f64()
+            ),
+            Err(e) => error!("Build failed: {}", e),
+        }
+
+        let mut build = lock(&self.build);
+        build.version += 1;
+        build.page = page.map_err(|e| e.to_string());
+    }
+
+    fn handle_connection(&self, mut stream: TcpStream) {
+        // Accepted sockets may inherit the listener's non-blocking mode
+        let setup = stream
+            .set_nonblocking(false)
+            .and_then(|_| stream.set_read_timeout(Some(READ_TIMEOUT)));
+        if let Err(e) = setup {
+            debug!("Failed to set up connection: {}", e);
+            return;
+        }
+
+        let (response, head_only) = match read_request(&stream) {
+            Ok(request) => (self.respond(&request), request.method == "HEAD"),
+            Err(message) => (Response::text(400, message), false),
+        };
+        if let Err(e) = response.write_to(&mut stream, head_only) {
+            debug!("Failed to send response: {}", e);
+        }
+    }
+
+    fn respond(&self, request: &Request) -> Response {
+        if request.method != "GET" && request.method != "HEAD" {
+            return Response::text(405, "Only GET and HEAD are supported");
+        }
+
+        let path = request.target.split(['?', '#']).next().unwrap_or_default();
+        debug!("{} {}", request.method, path);
+        if path == "/" {
+            return Response::html(200, self.preview_page());
+        }
+        if path == VERSION_PATH {
+            return Response::text(200, lock(&self.build).version.to_string());
+        }
+        match path.strip_prefix(FILES_PREFIX) {
+            Some(name) if percent_decode(name).as_deref() == Some(self.document_name.as_str()) => {
+                self.document()
+            }
+            Some(relative) => self.file(relative),
+            None => Response::not_found(),
+        }
+    }
+
+    /// The document as md2pdf prints it, or the reason it failed to build
+    fn document(&self) -> Response {
+        let build = lock(&self.build);
+        match &build.page {
+            Ok(page) => Response {
+                content_security_policy: content_security_policy(
+                    self.options.pdf_config.network_policy,
+                ),
+                ..Response::html(200, page.clone())
+            },
+            Err(message) => Response::html(500, error_page(message)),
+        }
+    }
+
+    /// A file the document references
+    ///
+    /// Only files inside the base directory are served, and none when the
+    /// network policy would keep the PDF from loading them.
+    fn file(&self, relative: &str) -> Response {
+        if self.options.pdf_config.network_policy == NetworkPolicy::Offline {
+            return Response::not_found();
+        }
+        let Some(path) = resolve_file(&self.base_dir, relative) else {
+            return Response::not_found();
+        };
+        match fs::read(&path) {
+            Ok(body) => Response {
+                status: 200,
+                content_type: content_type(&path),
+                body,
+                content_security_policy: None,
+            },
+            Err(e) => {
+                warn!("Failed to read {}: {}", path.display(), e);
+                Response::not_found()
+            }
+        }
+    }
+
+    /// The page showing the document in a paper-sized frame
+    fn preview_page(&self) -> String {
+        let config = &self.options.pdf_config;
+        let (width, height) = config.page_size();
+        let margins = format!(
+            "{}in {}in {}in {}in",
+            config.margin_top, config.margin_right, config.margin_bottom, config.margin_left
+        );
+        let file_name = self
+            .input
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <title>{title} - md2pdf preview</title>
+    <style>{css}</style>
+</head>
+<body>
+<header><span>{title} &middot; {width}in &times; {height}in</span><span id="status">Watching for changes</span></header>
+<iframe id="sheet" src="{src}" title="{title}" style="width: {width}in; height: {height}in" data-margins="{margins}" data-version="{version}" data-version-url="{version_url}"></iframe>
+<script>{script}</script>
+</body>
+</html>"#,
+            title = escape_html(&file_name),
+            css = PREVIEW_CSS,
+            width = width,
+            height = height,
+            src = escape_html(&format!(
+                "{}{}",
+                FILES_PREFIX,
+                percent_encode(&self.document_name)
+            )),
+            margins = margins,
+            version = lock(&self.build).version,
+            version_url = VERSION_PATH,
+            script = PREVIEW_SCRIPT,
+        )
+    }
+}
+
+/// Lock a mutex, recovering the data if another thread panicked while holding it
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// The parts of an HTTP request the server looks at
+struct Request {
+    method: String,
+    target: String,
+}
+
+/// Read an HTTP request head, ignoring its headers
+fn read_request(stream: &TcpStream) -> std::result::Result<Request, String> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader
+        .read_line(&mut request_line)
+        .map_err(|e| format!("Failed to read request: {}", e))?;
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target), Some(_version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err("Malformed request line".to_string());
+    };
+
+    // Skip headers up to the blank line ending the request head
+    let mut head_size = request_line.len();
+    loop {
+        let mut line = String::new();
+        let read = reader
+            .read_line(&mut line)
+            .map_err(|e| format!("Failed to read request: {}", e))?;
+        head_size += read;
+        if read == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if head_size > MAX_REQUEST_HEAD {
+            return Err("Request head too large".to_string());
+        }
+    }
+
+    Ok(Request {
+        method: method.to_string(),
+        target: target.to_string(),
+    })
+}
+
+/// An HTTP response, sent with `Connection: close`
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+    content_security_policy: Option<&'static str>,
+}
+
+impl Response {
+    fn html(status: u16, body: String) -> Self {
+        Self {
+            status,
+            content_type: "text/html; charset=utf-8",
+            body: body.into_bytes(),
+            content_security_policy: None,
+        }
+    }
+
+    fn text(status: u16, body: impl Into<String>) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: body.into().into_bytes(),
+            content_security_policy: None,
+        }
+    }
+
+    fn not_found() -> Self {
+        Self::text(404, "Not found")
+    }
+
+    fn write_to(&self, stream: &mut impl Write, head_only: bool) -> io::Result<()> {
+        let reason = match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            _ => "Internal Server Error",
+        };
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n",
+            self.status,
+            reason,
+            self.content_type,
+            self.body.len()
+        );
+        if let Some(policy) = self.content_security_policy {
+            head.push_str(&format!("Content-Security-Policy: {}\r\n", policy));
+        }
+        head.push_str("\r\n");
+
+        stream.write_all(head.as_bytes())?;
+        if !head_only {
+            stream.write_all(&self.body)?;
+        }
+        stream.flush()
+    }
+}
+
+/// A Content-Security-Policy giving the browser the network access the
+/// policy gives Chrome when printing
+fn content_security_policy(policy: NetworkPolicy) -> Option<&'static str> {
+    match policy {
+        NetworkPolicy::Offline => Some("default-src 'unsafe-inline' data: blob:"),
+        NetworkPolicy::LocalFiles => Some("default-src 'self' 'unsafe-inline' data: blob:"),
+        NetworkPolicy::AllowAll => None,
+    }
+}
+
+/// The page shown in place of a document that failed to build
+fn error_page(message: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="UTF-8">
+    <title>Build failed</title>
+</head>
+<body style="font-family: system-ui, sans-serif; color: #b00020;">
+<h1>Build failed</h1>
+<pre style="white-space: pre-wrap;">{}</pre>
+<p>The preview reloads when the sources change.</p>
+</body>
+</html>"#,
+        escape_html(message)
+    )
+}
+
+/// Map a URL path under [`FILES_PREFIX`] to a file in `base_dir`
+///
+/// Paths climbing out of the directory, directly or through symbolic links,
+/// are refused.
+fn resolve_file(base_dir: &Path, relative: &str) -> Option<PathBuf> {
+    let decoded = percent_decode(relative)?;
+    let mut path = base_dir.to_path_buf();
+    for segment in decoded.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return None,
+            segment if segment.contains('\\') => return None,
+            segment => path.push(segment),
+        }
+    }
+    let path = path.canonicalize().ok()?;
+    (path.starts_with(base_dir) && path.is_file()).then_some(path)
+}
+
+/// Decode `%XX` escapes in a URL path, `None` if they are malformed or
+/// don't decode to UTF-8
+fn percent_decode(s: &str) -> Option<String> {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Escape a file name for use as a URL path segment
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+/// Content type of a served file, from its extension
+fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt" | "md") => "text/plain; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("avif") => "image/avif",
+        Some("ico") => "image/x-icon",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("ttf") => "font/ttf",
+        Some("otf") => "font/otf",
+        Some("pdf") => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    /// Send a request and split the response into status line and body
+    fn get(server: &PreviewServer, path: &str) -> (String, String) {
+        let mut stream = TcpStream::connect(server.address).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).unwrap();
+        let response = String::from_utf8_lossy(&response);
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        (head.lines().next().unwrap().to_string(), body.to_string())
+    }
+
+    /// Stops a server when dropped, even if an assertion failed
+    struct StopOnDrop<'a>(&'a AtomicBool);
+
+    impl Drop for StopOnDrop<'_> {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_percent_coding() {
+        assert_eq!(
+            percent_decode("my%20notes.md").as_deref(),
+            Some("my notes.md")
+        );
+        assert_eq!(percent_decode("caf%C3%A9").as_deref(), Some("café"));
+        assert_eq!(percent_decode("bad%2"), None);
+        assert_eq!(percent_decode("bad%zz"), None);
+        assert_eq!(percent_encode("my notes.html"), "my%20notes.html");
+        assert_eq!(percent_encode("café"), "caf%C3%A9");
+    }
+
+    #[test]
+    fn test_resolve_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let root = temp_dir.path().canonicalize().unwrap();
+        let base_dir = root.join("docs");
+        fs::create_dir_all(base_dir.join("img")).unwrap();
+        fs::write(base_dir.join("img/logo.png"), b"png").unwrap();
+        fs::write(root.join("secret.txt"), "secret").unwrap();
+
+        assert_eq!(
+            resolve_file(&base_dir, "img/logo.png"),
+            Some(base_dir.join("img/logo.png"))
+        );
+        assert_eq!(
+            resolve_file(&base_dir, "./img//logo%2Epng"),
+            Some(base_dir.join("img/logo.png"))
+        );
+        assert_eq!(resolve_file(&base_dir, "../secret.txt"), None);
+        assert_eq!(resolve_file(&base_dir, "%2E%2E/secret.txt"), None);
+        assert_eq!(resolve_file(&base_dir, "img"), None);
+        assert_eq!(resolve_file(&base_dir, "missing.png"), None);
+    }
+
+    #[test]
+    fn test_content_type() {
+        assert_eq!(
+            content_type(Path::new("a/style.CSS")),
+            "text/css; charset=utf-8"
+        );
+        assert_eq!(content_type(Path::new("logo.svg")), "image/svg+xml");
+        assert_eq!(
+            content_type(Path::new("data.bin")),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn test_preview_server() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        let input = dir.join("guide.md");
+        fs::write(&input, "# Guide\n\n![logo](logo.png)\n").unwrap();
+        fs::write(dir.join("logo.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+
+        let options = ConversionOptions::default();
+        let server = PreviewServer::bind(&input, &options, 0).unwrap();
+        let stop = AtomicBool::new(false);
+        thread::scope(|scope| {
+            scope.spawn(|| server.run(&stop));
+            let _stop = StopOnDrop(&stop);
+
+            let (status, page) = get(&server, "/");
+            assert_eq!(status, "HTTP/1.1 200 OK");
+            assert!(page.contains(r#"src="/files/guide.html""#));
+            assert!(page.contains(r#"data-version="1""#));
+            assert!(page.contains(&format!(r#"data-version-url="{}""#, VERSION_PATH)));
+
+            // The document is exactly the HTML that is printed
+            let (status, document) = get(&server, "/files/guide.html");
+            assert_eq!(status, "HTTP/1.1 200 OK");
+            let expected =
+                convert_markdown_to_html_with(&Renderer::new(), &input, &options).unwrap();
+            assert_eq!(document, expected);
+
+            assert_eq!(get(&server, "/files/logo.png").0, "HTTP/1.1 200 OK");
+            assert_eq!(
+                get(&server, "/files/../guide.md").0,
+                "HTTP/1.1 404 Not Found"
+            );
+            assert_eq!(get(&server, VERSION_PATH).1, "1");
+
+            // Editing the document publishes a new version
+            fs::write(&input, "# Guide\n\nRevised.\n").unwrap();
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while get(&server, VERSION_PATH).1 == "1" {
+                assert!(Instant::now() < deadline, "document was not rebuilt");
+                thread::sleep(Duration::from_millis(50));
+            }
+            assert!(get(&server, "/files/guide.html").1.contains("Revised."));
+        });
+    }
+
+    #[test]
+    fn test_build_failure_is_shown() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let input = temp_dir.path().join("empty.md");
+        fs::write(&input, "\n").unwrap();
+
+        let server = PreviewServer::bind(&input, &ConversionOptions::default(), 0).unwrap();
+        let response = server.document();
+        assert_eq!(response.status, 500);
+        assert!(String::from_utf8(response.body)
+            .unwrap()
+            .contains("Build failed"));
+    }
+}
@@ -4,9 +4,15 @@
 //! optimized for professional business documents with smart page breaks.
 
 use crate::error::{Md2PdfError, Result};
+use crate::pdf::PdfConfig;
+use log::warn;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 
+/// Placeholders filled in by Chrome when printing headers and footers
+const PAGE_PLACEHOLDERS: [&str; 5] = ["pageNumber", "totalPages", "title", "date", "url"];
+
 /// Default CSS for professional PDF output with smart page break handling
 pub const DEFAULT_CSS: &str = r#"
 /* Professional PDF Styling with Smart Page Breaks */
@@ -18,20 +24,6 @@ pub const DEFAULT_CSS: &str = r#"
     box-sizing: border-box;
 }
 
-/* Page setup for print */
-@page {
-    size: A4;
-    margin: 2.5cm 2cm;
-
-    @top-center {
-        content: string(doctitle);
-    }
-
-    @bottom-center {
-        content: counter(page);
-    }
-}
-
 /* Body and typography */
 body {
     font-family: 'Segoe UI', 'Helvetica Neue', Arial, sans-serif;
@@ -67,7 +59,6 @@ h1 {
     border-bottom: 3px solid #2c5aa0;
     padding-bottom: 0.3em;
     margin-top: 0;
-    string-set: doctitle content();
 }
 
 h2 {
@@ -172,6 +163,119 @@ pre code {
     padding: 0;
 }
 
+/* Code listings: line numbers and highlighted lines. These may split
+   across pages between lines; the header row repeats on every page, and
+   on the first one the label is drawn over it. */
+.code-listing {
+    position: relative;
+    margin-bottom: 1em;
+}
+
+.code-listing-label,
+.code-continued {
+    height: 1.8em;
+    line-height: 1.8em;
+    padding: 0 0.8em;
+    border: none;
+    background-color: #e8ecf0;
+    color: #555;
+    font-family: 'Consolas', 'Monaco', 'Courier New', monospace;
+    font-size: 0.8em;
+    font-weight: normal;
+    text-align: left;
+}
+
+.code-listing-label {
+    position: absolute;
+    top: 0;
+    left: 0;
+    right: 0;
+    z-index: 1;
+    border-radius: 4px 4px 0 0;
+}
+
+.code-listing-table {
+    width: 100%;
+    margin: 0;
+    border: 1px solid #e0e0e0;
+    border-left: 4px solid #2c5aa0;
+    border-collapse: collapse;
+    background-color: #f5f7f9;
+    page-break-inside: auto;
+    break-inside: auto;
+    font-family: 'Consolas', 'Monaco', 'Courier New', monospace;
+    font-size: 0.9em;
+    line-height: 1.4;
+}
+
+.code-listing-table td,
+.code-listing-table tr:nth-child(even) {
+    border: none;
+    background-color: transparent;
+}
+
+.code-listing-table thead {
+    display: table-header-group;
+}
+
+.code-listing-table td {
+    padding: 0 0.8em;
+    height: 1.4em;
+    vertical-align: top;
+}
+
+.code-line {
+    page-break-inside: avoid;
+    break-inside: avoid;
+}
+
+.code-listing-table tr.code-line-highlighted {
+    background-color: #fff5b1;
+}
+
+.line-number {
+    width: 1%;
+    color: #999;
+    text-align: right;
+    white-space: nowrap;
+    border-right: 1px solid #e0e0e0;
+    user-select: none;
+}
+
+.line-code {
+    white-space: pre-wrap;
+    word-break: break-all;
+}
+
+.line-code code {
+    background-color: transparent;
+    padding: 0;
+    font-size: 1em;
+}
+
+/* Diagrams */
+.diagram {
+    margin: 1em 0;
+    text-align: center;
+}
+
+.diagram svg {
+    max-width: 100%;
+    height: auto;
+}
+
+/* Math */
+math {
+    font-size: 1.1em;
+}
+
+.math-display {
+    display: block;
+    margin: 1em 0;
+    text-align: center;
+    overflow-x: auto;
+}
+
 /* Blockquotes */
 blockquote {
     border-left: 4px solid #2c5aa0;
@@ -273,6 +377,71 @@ figure, .no-break {
     break-inside: avoid;
 }
 
+/* Table of contents */
+.toc {
+    margin-bottom: 2em;
+    page-break-after: always;
+    break-after: page;
+}
+
+.toc-title {
+    font-size: 1.8em;
+    font-weight: 600;
+    color: #1a1a1a;
+    margin-bottom: 0.5em;
+    text-align: left;
+}
+
+.toc-list {
+    list-style: none;
+    margin-left: 0;
+    page-break-inside: auto;
+    break-inside: auto;
+}
+
+.toc-list a {
+    display: flex;
+    align-items: baseline;
+    color: #333;
+    border-bottom: none;
+}
+
+.toc-leader {
+    flex: 1;
+    margin: 0 0.4em;
+    border-bottom: 1px dotted #999;
+}
+
+.toc-page {
+    min-width: 2em;
+    text-align: right;
+}
+
+.toc-level-1 { font-weight: 600; margin-top: 0.5em; }
+.toc-level-2 { margin-left: 1.5em; }
+.toc-level-3 { margin-left: 3em; }
+.toc-level-4 { margin-left: 4.5em; }
+.toc-level-5 { margin-left: 6em; }
+.toc-level-6 { margin-left: 7.5em; }
+
+/* Chapters of a merged document each start on a new page */
+.chapter + .chapter {
+    page-break-before: always;
+    break-before: page;
+}
+
+.chapter > h1:first-child {
+    margin-top: 0;
+}
+
+/* Book parts open with a page showing the part title */
+.part-title {
+    font-size: 2.6em;
+    text-align: center;
+    border-bottom: none;
+    padding-top: 35vh;
+}
+
 /* First page special styling */
 body > h1:first-child {
     margin-top: 0;
@@ -297,7 +466,9 @@ pub fn generate_html(content: &str, css: &str, html_title: &str) -> String {
 {}
 </body>
 </html>"#,
-        html_title, css, content
+        escape_html(html_title),
+        css,
+        content
     )
 }
 
@@ -312,6 +483,84 @@ pub fn load_css(css_path: Option<&Path>) -> Result<String> {
     }
 }
 
+/// Load a header or footer template given inline or as a file path
+///
+/// If `value` names an existing file its contents are used, otherwise the
+/// value itself is taken as the template HTML.
+pub fn load_page_template(value: &str) -> Result<String> {
+    let path = Path::new(value);
+    if path.is_file() {
+        fs::read_to_string(path).map_err(|e| Md2PdfError::FileRead {
+            path: path.to_path_buf(),
+            source: e,
+        })
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+/// Expand `{{placeholder}}` markers in a header or footer template
+///
+/// `{{pageNumber}}`, `{{totalPages}}`, `{{title}}`, `{{date}}` and `{{url}}`
+/// become the elements Chrome fills in while printing, except that a `date`
+/// in `variables` (from front matter or `--var`) is used instead of the
+/// print date. Any other name is looked up in `variables` and inserted
+/// HTML-escaped; unknown names expand to nothing. The result is wrapped in a full-width container with a
+/// readable default font size, since Chrome renders templates unstyled. The
+/// container is padded by the left and right page margins of `pdf_config`,
+/// as the template spans the whole page width, so the text lines up with
+/// the body.
+pub fn expand_page_template(
+    template: &str,
+    variables: &BTreeMap<String, String>,
+    pdf_config: &PdfConfig,
+) -> String {
+    let mut expanded = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|end| start + end) else {
+            break;
+        };
+        expanded.push_str(&rest[..start]);
+
+        let name = rest[start + 2..end].trim();
+        match variables.get(name) {
+            Some(value) if name == "date" || !PAGE_PLACEHOLDERS.contains(&name) => {
+                expanded.push_str(&escape_html(value));
+            }
+            _ if PAGE_PLACEHOLDERS.contains(&name) => {
+                expanded.push_str(&format!(r#"<span class="{}"></span>"#, name));
+            }
+            _ => warn!("Unknown template variable: {{{{{}}}}}", name),
+        }
+
+        rest = &rest[end + 2..];
+    }
+    expanded.push_str(rest);
+
+    format!(
+        r#"<div style="width: 100%; font-size: 9pt; padding: 0 {:.3}in 0 {:.3}in;">{}</div>"#,
+        pdf_config.margin_right, pdf_config.margin_left, expanded
+    )
+}
+
+/// Escape text for inclusion in HTML content or attribute values
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -319,8 +568,9 @@ mod tests {
     #[test]
     fn test_default_css_not_empty() {
         assert!(!DEFAULT_CSS.is_empty());
-        assert!(DEFAULT_CSS.contains("@page"));
         assert!(DEFAULT_CSS.contains("page-break-inside"));
+        // Page size, margins and page numbers come from PdfConfig
+        assert!(!DEFAULT_CSS.contains("@page"));
     }
 
     #[test]
@@ -333,6 +583,13 @@ mod tests {
         assert!(html.contains("<!DOCTYPE html>"));
         assert!(html.contains(content));
         assert!(html.contains(css));
+        assert!(html.contains("<title>Test Document</title>"));
+    }
+
+    #[test]
+    fn test_generate_html_escapes_title() {
+        let html = generate_html("", "", "R&D <draft>");
+        assert!(html.contains("<title>R&amp;D &lt;draft&gt;</title>"));
     }
 
     #[test]
@@ -340,4 +597,56 @@ mod tests {
         let css = load_css(None).unwrap();
         assert_eq!(css, DEFAULT_CSS);
     }
+
+    #[test]
+    fn test_expand_page_template() {
+        let mut variables = BTreeMap::new();
+        variables.insert("author".to_string(), "Ada & Co".to_string());
+        variables.insert("version".to_string(), "1.2".to_string());
+
+        let expanded = expand_page_template(
+            "{{author}} v{{ version }} - Page {{pageNumber}} of {{totalPages}}{{missing}}",
+            &variables,
+            &PdfConfig::default(),
+        );
+        assert!(expanded.contains("padding: 0 0.787in 0 0.787in;"));
+        assert!(expanded.contains("Ada &amp; Co v1.2 - Page "));
+        assert!(expanded
+            .contains(r#"<span class="pageNumber"></span> of <span class="totalPages"></span>"#));
+        assert!(!expanded.contains("missing"));
+        assert!(!expanded.contains("{{"));
+    }
+
+    #[test]
+    fn test_expand_page_template_document_date() {
+        let template = "{{date}} / {{title}}";
+        let expanded = expand_page_template(template, &BTreeMap::new(), &PdfConfig::default());
+        assert!(expanded.contains(r#"<span class="date"></span>"#));
+
+        let mut variables = BTreeMap::new();
+        variables.insert("date".to_string(), "2024-03-01".to_string());
+        variables.insert("title".to_string(), "Guide".to_string());
+        let expanded = expand_page_template(template, &variables, &PdfConfig::default());
+        assert!(expanded.contains(r#"2024-03-01 / <span class="title"></span>"#));
+    }
+
+    #[test]
+    fn test_expand_page_template_unterminated_placeholder() {
+        let expanded =
+            expand_page_template("{{date}} {{oops", &BTreeMap::new(), &PdfConfig::default());
+        assert!(expanded.contains(r#"<span class="date"></span> {{oops"#));
+    }
+
+    #[test]
+    fn test_load_page_template_inline_or_file() {
+        assert_eq!(
+            load_page_template("<b>{{title}}</b>").unwrap(),
+            "<b>{{title}}</b>"
+        );
+
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, b"<i>footer</i>").unwrap();
+        let loaded = load_page_template(file.path().to_str().unwrap()).unwrap();
+        assert_eq!(loaded, "<i>footer</i>");
+    }
 }
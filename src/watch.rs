@@ -0,0 +1,308 @@
+//! Watching source files for changes
+//!
+//! Watch mode polls file sizes and modification times instead of relying on
+//! platform notification APIs. Polling a handful of files is cheap, copes
+//! with editors that save by replacing the file, and notices files that did
+//! not exist yet, such as an image that is referenced before it is created.
+
+use crate::html;
+use crate::project::Book;
+use crate::ConversionOptions;
+use std::collections::BTreeMap;
+use std::fs;
+use std::ops::ControlFlow;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How often watched files are checked
+pub const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// How long files must stay unchanged before a change is reported, so a
+/// burst of writes (an editor saving several files) triggers one rebuild
+pub const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// How often long-running modes check that an idle browser is still
+/// reachable; see [`crate::pdf::Renderer::keep_alive`]
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Modification time and size of a file, `None` while it doesn't exist
+type Stamp = Option<(Option<SystemTime>, u64)>;
+
+fn stamp(path: &Path) -> Stamp {
+    fs::metadata(path)
+        .ok()
+        .map(|metadata| (metadata.modified().ok(), metadata.len()))
+}
+
+/// Polls a set of files for changes
+#[derive(Debug)]
+pub struct FileWatcher {
+    files: BTreeMap<PathBuf, Stamp>,
+    poll_interval: Duration,
+    debounce: Duration,
+}
+
+impl FileWatcher {
+    /// Watch the given files, recording their current state
+    pub fn new(files: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut watcher = Self {
+            files: BTreeMap::new(),
+            poll_interval: POLL_INTERVAL,
+            debounce: DEBOUNCE,
+        };
+        watcher.set_files(files);
+        watcher
+    }
+
+    /// Use a different polling interval and debounce delay
+    pub fn with_timing(mut self, poll_interval: Duration, debounce: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self.debounce = debounce;
+        self
+    }
+
+    /// Files currently watched
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.files.keys().map(PathBuf::as_path)
+    }
+
+    /// Replace the set of watched files
+    ///
+    /// Files that were already watched keep their recorded state, so a change
+    /// made while a rebuild was running is still reported by the next poll.
+    pub fn set_files(&mut self, files: impl IntoIterator<Item = PathBuf>) {
+        let mut previous = std::mem::take(&mut self.files);
+        for path in files {
+            let state = previous.remove(&path).unwrap_or_else(|| stamp(&path));
+            self.files.insert(path, state);
+        }
+    }
+
+    /// Files that changed, appeared or disappeared since the last poll
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut changed = Vec::new();
+        for (path, state) in &mut self.files {
+            let current = stamp(path);
+            if current != *state {
+                *state = current;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+
+    /// Block until watched files change, then return the changed files
+    ///
+    /// Changes are collected until no file has changed for the debounce
+    /// delay. `on_idle` runs between polls; returning
+    /// [`ControlFlow::Break`] from it stops waiting and yields `None`.
+    pub fn wait_for_change(
+        &mut self,
+        mut on_idle: impl FnMut() -> ControlFlow<()>,
+    ) -> Option<Vec<PathBuf>> {
+        let mut changed: Vec<PathBuf> = Vec::new();
+        let mut last_change = None;
+        loop {
+            let now_changed = self.poll();
+            if !now_changed.is_empty() {
+                for path in now_changed {
+                    if !changed.contains(&path) {
+                        changed.push(path);
+                    }
+                }
+                last_change = Some(Instant::now());
+            } else if last_change.is_some_and(|at| at.elapsed() >= self.debounce) {
+                return Some(changed);
+            }
+
+            if on_idle().is_break() {
+                return None;
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+}
+
+/// Files read when converting a single markdown file
+///
+/// These are the markdown file itself, the local images it references, the
+/// custom stylesheet and the Mermaid bundle. Images resolve against
+/// `options.base_dir` or the file's directory, as they do in a conversion.
+pub fn document_files(input: &Path, options: &ConversionOptions) -> Vec<PathBuf> {
+    let base_dir = options
+        .base_dir
+        .clone()
+        .or_else(|| input.parent().map(Path::to_path_buf));
+    let mut files = Vec::new();
+    add_markdown(&mut files, input, base_dir.as_deref());
+    add_resources(&mut files, options);
+    files
+}
+
+/// Files read when merging markdown files into one document
+///
+/// Like [`document_files`], except each file's images resolve against its
+/// own directory.
+pub fn merged_files(inputs: &[impl AsRef<Path>], options: &ConversionOptions) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let input = input.as_ref();
+        add_markdown(&mut files, input, input.parent());
+    }
+    add_resources(&mut files, options);
+    files
+}
+
+/// Files read when converting a book, including its manifest and the
+/// stylesheets of individual chapters
+pub fn book_files(book: &Book, options: &ConversionOptions) -> Vec<PathBuf> {
+    let markdown_files: Vec<_> = book
+        .title_page
+        .iter()
+        .chain(book.chapters().map(|chapter| &chapter.path))
+        .collect();
+    let mut files = vec![book.manifest.clone()];
+    files.extend(merged_files(
+        &markdown_files,
+        &book.conversion_options(options),
+    ));
+    for css in book.chapters().filter_map(|chapter| chapter.css.clone()) {
+        push_unique(&mut files, css);
+    }
+    files
+}
+
+/// Add a markdown file and the local images it references
+///
+/// A file that cannot be read is still listed, so fixing it triggers a
+/// rebuild.
+fn add_markdown(files: &mut Vec<PathBuf>, path: &Path, base_dir: Option<&Path>) {
+    push_unique(files, path.to_path_buf());
+    let Ok(markdown) = fs::read_to_string(path) else {
+        return;
+    };
+    let base_dir = base_dir.unwrap_or(Path::new("."));
+    if let Ok(images) = html::local_images(&markdown, base_dir) {
+        for image in images {
+            push_unique(files, image);
+        }
+    }
+}
+
+/// Add the stylesheet and scripts every conversion reads
+fn add_resources(files: &mut Vec<PathBuf>, options: &ConversionOptions) {
+    if let Some(css) = options.css_path() {
+        push_unique(files, PathBuf::from(css));
+    }
+    if let Some(script) = &options.mermaid_script {
+        push_unique(files, script.clone());
+    }
+}
+
+fn push_unique(files: &mut Vec<PathBuf>, path: PathBuf) {
+    if !files.contains(&path) {
+        files.push(path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fast(watcher: FileWatcher) -> FileWatcher {
+        watcher.with_timing(Duration::from_millis(10), Duration::from_millis(30))
+    }
+
+    #[test]
+    fn test_poll_reports_changes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let doc = temp_dir.path().join("doc.md");
+        let image = temp_dir.path().join("image.png");
+        fs::write(&doc, "# Title\n").unwrap();
+
+        let mut watcher = FileWatcher::new([doc.clone(), image.clone()]);
+        assert!(watcher.poll().is_empty());
+
+        fs::write(&doc, "# Title\n\nMore text.\n").unwrap();
+        assert_eq!(watcher.poll(), vec![doc.clone()]);
+        assert!(watcher.poll().is_empty());
+
+        // Files that appear or disappear count as changed
+        fs::write(&image, b"png").unwrap();
+        assert_eq!(watcher.poll(), vec![image.clone()]);
+        fs::remove_file(&doc).unwrap();
+        assert_eq!(watcher.poll(), vec![doc]);
+    }
+
+    #[test]
+    fn test_set_files_keeps_pending_changes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let doc = temp_dir.path().join("doc.md");
+        let style = temp_dir.path().join("style.css");
+        fs::write(&doc, "# Title\n").unwrap();
+
+        let mut watcher = FileWatcher::new([doc.clone()]);
+        fs::write(&doc, "# Changed title\n").unwrap();
+        watcher.set_files([doc.clone(), style.clone()]);
+
+        assert_eq!(watcher.poll(), vec![doc.clone()]);
+        assert_eq!(watcher.files().collect::<Vec<_>>(), vec![doc, style]);
+    }
+
+    #[test]
+    fn test_wait_for_change_debounces() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let doc = temp_dir.path().join("doc.md");
+        let style = temp_dir.path().join("style.css");
+        fs::write(&doc, "# Title\n").unwrap();
+
+        let mut watcher = fast(FileWatcher::new([doc.clone(), style.clone()]));
+        let mut polls = 0;
+        let changed = watcher.wait_for_change(|| {
+            polls += 1;
+            match polls {
+                1 => fs::write(&doc, "# New title\n").unwrap(),
+                2 => fs::write(&style, "body {}").unwrap(),
+                _ => {}
+            }
+            ControlFlow::Continue(())
+        });
+        assert_eq!(changed, Some(vec![doc, style]));
+
+        let mut watcher = fast(FileWatcher::new(Vec::new()));
+        assert_eq!(watcher.wait_for_change(|| ControlFlow::Break(())), None);
+    }
+
+    #[test]
+    fn test_document_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().canonicalize().unwrap();
+        let doc = dir.join("doc.md");
+        fs::write(
+            &doc,
+            "# Title\n\n![diagram](img/diagram.png) ![remote](https://example.com/a.png)\n",
+        )
+        .unwrap();
+        let options = ConversionOptions {
+            custom_css_path: Some(dir.join("style.css").to_string_lossy().to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            document_files(&doc, &options),
+            vec![
+                doc.clone(),
+                dir.join("img/diagram.png"),
+                dir.join("style.css")
+            ]
+        );
+
+        // A missing input is still watched so it is picked up once it exists
+        let missing = dir.join("missing.md");
+        assert_eq!(
+            document_files(&missing, &ConversionOptions::default()),
+            vec![missing]
+        );
+    }
+}